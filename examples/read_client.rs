@@ -4,7 +4,12 @@ use rdma_rs::{
     ffi::{ibv_access_flags, ibv_send_wr, ibv_sge, ibv_wc, ibv_wr_opcode},
     ibv::{IbvContext, IbvCq, IbvMr, IbvPd, IbvQp},
 };
-use std::{net::TcpStream, time::SystemTime};
+use std::{
+    net::TcpStream,
+    sync::{Arc, Barrier},
+    thread,
+    time::SystemTime,
+};
 
 #[derive(Parser, Debug)]
 #[clap(about, version, author)]
@@ -27,8 +32,27 @@ struct Args {
 
 fn main() {
     println!("hello world from read_client");
+    let args = Arc::new(Args::parse());
+    // One connection and QP per thread, all threads start reading together.
+    let barrier = Arc::new(Barrier::new(args.qp_threads));
+    let handles: Vec<_> = (0..args.qp_threads)
+        .map(|_| {
+            let args = args.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || run_qp(&args, &barrier))
+        })
+        .collect();
+    let mut total = 0f64;
+    for (i, handle) in handles.into_iter().enumerate() {
+        let throughput = handle.join().unwrap();
+        println!("thread {} rdma read throughput: {:.3}Gbps", i, throughput);
+        total += throughput;
+    }
+    println!("rdma read throughput: {:.3}Gbps", total);
+}
+
+fn run_qp(args: &Args, barrier: &Barrier) -> f64 {
     // Create RDMA resources.
-    let args = Args::parse();
     let mut buf = vec![0_u8; args.buf_size].into_boxed_slice();
     let context = IbvContext::new(Some(&args.dev)).unwrap();
     let pd = IbvPd::new(&context).unwrap();
@@ -41,12 +65,14 @@ fn main() {
     // Connection.
     let mut stream = TcpStream::connect(&args.addr).unwrap();
     let my_lid = context.query_port(args.ib_port).unwrap().lid();
-    let remote_mem = connect(&mut stream, &qp, my_lid, &buf, &mr);
+    let remote_mem = connect(&mut stream, &qp, my_lid, &buf, &mr).unwrap();
     let my_mem = MemInfo::new(buf.as_ptr() as u64, buf.len(), mr.rkey());
     //RDMA read.
-    rdma_read(&qp, &cq, &mr, my_mem, remote_mem, &args);
+    barrier.wait();
+    let throughput = rdma_read(&qp, &cq, &mr, my_mem, remote_mem, args);
     // Close Connection.
-    client_disconnect(stream);
+    client_disconnect(stream).unwrap();
+    throughput
 }
 
 fn rdma_read(
@@ -56,7 +82,7 @@ fn rdma_read(
     my_mem: MemInfo,
     remote_mem: MemInfo,
     args: &Args,
-) {
+) -> f64 {
     if my_mem.len < remote_mem.len {
         println!("Memory len error. Remote len < my len");
     }
@@ -119,6 +145,5 @@ fn rdma_read(
     println!("duration {}us", duration);
     let duration = (duration as f64) / 1e6;
     let total_size = (8 * num * msg_len) as f64 / (1024f64 * 1024f64 * 1024f64);
-    let throughput = total_size / duration;
-    println!("rdma read throughput: {:.3}Gbps", throughput);
+    total_size / duration
 }
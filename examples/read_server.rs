@@ -6,6 +6,8 @@ use rdma_rs::{
 };
 use std::{
     net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
     vec::Vec,
 };
 #[derive(Parser, Debug)]
@@ -33,11 +35,24 @@ fn listen(listener: &TcpListener) -> TcpStream {
     }
 }
 fn main() {
-    // Create RDMA resources.
-    let args = Args::parse();
+    let args = Arc::new(Args::parse());
     let mut listener = TcpListener::bind(&args.addr).unwrap();
     println!("listen on {}", args.addr);
-    let mut stream = listen(&listener);
+    // Serve every client thread on its own connection and QP.
+    let handles: Vec<_> = (0..args.qp_threads)
+        .map(|_| {
+            let stream = listen(&listener);
+            let args = args.clone();
+            thread::spawn(move || serve(&args, stream))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+fn serve(args: &Args, mut stream: TcpStream) {
+    // Create RDMA resources.
     let mut buf = vec![1_u8; args.buf_size].into_boxed_slice();
     let context = IbvContext::new(Some(&args.dev)).unwrap();
     let pd = IbvPd::new(&context).unwrap();
@@ -49,8 +64,8 @@ fn main() {
     let qp = IbvQp::new(&pd, &cq, &cq, 1, args.tx_depth as u32, 10, 1, 1, 0).unwrap();
     // Connection.
     let my_lid = context.query_port(args.ib_port).unwrap().lid();
-    let remote_mem = connect(&mut stream, &qp, my_lid, &buf, &mr);
+    let remote_mem = connect(&mut stream, &qp, my_lid, &buf, &mr).unwrap();
 
     // Wait for disonnection.
-    server_disconnect(stream);
+    server_disconnect(stream).unwrap();
 }
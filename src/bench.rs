@@ -0,0 +1,172 @@
+use std::cell::RefCell;
+
+use clap::{App, ArgMatches, Args, FromArgMatches};
+use serde::Serialize;
+
+use crate::{
+    environment::Environment,
+    error::{self, Error},
+    measure::{self, MeasureArgs, Measurement, Sample},
+    progress::{Progress, Reporter},
+    result::Parameters,
+};
+
+/// A benchmark runs in four phases. `run` is one measured iteration and is
+/// repeated by the measurement engine.
+pub trait Benchmark {
+    fn setup(&mut self) -> error::Result<()> {
+        Ok(())
+    }
+    /// Called before every iteration, warmups included, outside of the
+    /// measurement, e.g. to evict caches.
+    fn prepare(&mut self) -> error::Result<()> {
+        Ok(())
+    }
+    /// `progress` counts the completed operations. If it has a deadline, the
+    /// workload repeats until then instead of stopping after one pass.
+    fn run(&mut self, progress: &Progress) -> error::Result<Sample>;
+    fn teardown(&mut self) -> error::Result<()> {
+        Ok(())
+    }
+    fn report(&self, measurement: &Measurement) {
+        println!("{}", measurement);
+    }
+    /// Passive sides of a benchmark pair can only run a fixed number of times.
+    fn max_iterations(&self) -> Option<usize> {
+        None
+    }
+    /// Called before setup when the benchmark runs as one of `workers` worker
+    /// threads, so that workers can pick distinct files, ports or parts of a
+    /// file.
+    fn set_worker(&mut self, _worker: usize, _workers: usize) {}
+    /// Adds the files and RDMA devices the benchmark uses to the environment
+    /// recorded with its result.
+    fn describe(&self, _env: &mut Environment) {}
+}
+
+/// Typed command line arguments that build a benchmark. They are serialized
+/// into the parameters of the benchmark result.
+pub trait BenchmarkArgs: Args + Serialize {
+    fn build(self) -> Box<dyn Benchmark>;
+}
+
+type Builder = fn(&ArgMatches) -> Result<(Box<dyn Benchmark>, Parameters), clap::Error>;
+
+/// A registered benchmark: its subcommand and how to build it from parsed
+/// arguments.
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub name: &'static str,
+    pub about: &'static str,
+    augment: fn(App<'_>) -> App<'_>,
+    build: Builder,
+}
+
+impl Entry {
+    /// The subcommand with the benchmark and measurement options.
+    pub fn command(&self) -> App<'static> {
+        MeasureArgs::augment_args((self.augment)(App::new(self.name).about(self.about)))
+    }
+    pub fn build(
+        &self,
+        matches: &ArgMatches,
+    ) -> Result<(Box<dyn Benchmark>, Parameters), clap::Error> {
+        (self.build)(matches)
+    }
+    /// Parses `args` as the options of the subcommand and measures the
+    /// benchmark on the calling thread.
+    pub fn run(&self, args: &[&str]) -> error::Result<Measurement> {
+        let argv = std::iter::once(self.name).chain(args.iter().copied());
+        let matches = self
+            .command()
+            .try_get_matches_from(argv)
+            .map_err(|e| Error::Config(e.to_string()))?;
+        let (mut bench, _) = self
+            .build(&matches)
+            .map_err(|e| Error::Config(e.to_string()))?;
+        let measure_args =
+            MeasureArgs::from_arg_matches(&matches).map_err(|e| Error::Config(e.to_string()))?;
+        execute(bench.as_mut(), &measure_args)
+    }
+}
+
+/// Two benchmarks that only run together, e.g. the server and the client of
+/// an RDMA read. The server is started first.
+#[derive(Clone, Copy)]
+pub struct Pair {
+    pub name: &'static str,
+    pub server: &'static str,
+    pub client: &'static str,
+}
+
+/// Benchmarks by name, in the order they were registered.
+#[derive(Clone, Default)]
+pub struct Registry {
+    entries: Vec<Entry>,
+    pairs: Vec<Pair>,
+}
+
+impl Registry {
+    pub fn register<A: BenchmarkArgs>(&mut self, name: &'static str, about: &'static str) {
+        assert!(self.get(name).is_none(), "benchmark {} registered twice", name);
+        self.entries.push(Entry {
+            name,
+            about,
+            augment: A::augment_args,
+            build: build_from::<A>,
+        });
+    }
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter()
+    }
+    /// Registers two benchmarks, registered before, as a pair that the
+    /// coordinator can run on two hosts.
+    pub fn register_pair(&mut self, name: &'static str, server: &'static str, client: &'static str) {
+        assert!(
+            self.get(server).is_some() && self.get(client).is_some(),
+            "pair {} of unknown benchmarks",
+            name
+        );
+        self.pairs.push(Pair { name, server, client });
+    }
+    pub fn pair(&self, name: &str) -> Option<&Pair> {
+        self.pairs.iter().find(|p| p.name == name)
+    }
+    pub fn pairs(&self) -> impl Iterator<Item = &Pair> {
+        self.pairs.iter()
+    }
+}
+
+fn build_from<A: BenchmarkArgs>(
+    matches: &ArgMatches,
+) -> Result<(Box<dyn Benchmark>, Parameters), clap::Error> {
+    let args = A::from_arg_matches(matches)?;
+    let parameters = match serde_json::to_value(&args) {
+        Ok(serde_json::Value::Object(map)) => map.into_iter().collect(),
+        _ => Parameters::new(),
+    };
+    Ok((args.build(), parameters))
+}
+
+/// Sets up the benchmark, measures it and tears it down again.
+pub fn execute(bench: &mut dyn Benchmark, args: &MeasureArgs) -> error::Result<Measurement> {
+    bench.setup()?;
+    let limit = bench.max_iterations();
+    let progress = Progress::new();
+    let reporter = args.report_interval().map(|i| Reporter::start(&progress, i));
+    let bench = RefCell::new(bench);
+    let res = measure::measure(
+        args,
+        limit,
+        &progress,
+        || bench.borrow_mut().prepare(),
+        |p| bench.borrow_mut().run(p),
+    );
+    let bench = bench.into_inner();
+    let intervals = reporter.map(Reporter::finish).unwrap_or_default();
+    bench.teardown()?;
+    Ok(res?.with_intervals(intervals))
+}
@@ -0,0 +1,71 @@
+//! Checksums for --verify, so that corrupted transfers and writes fail the run
+//! instead of reporting a throughput.
+use std::{
+    fs::File,
+    io::{self, Read},
+};
+
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+use crate::{
+    data::Stream,
+    error::{Error, Result},
+};
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+pub fn checksum(data: &[u8]) -> u64 {
+    xxh3_64(data)
+}
+
+pub fn file_checksum(path: &str) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            return Ok(hasher.digest());
+        }
+        hasher.update(&buf[..len]);
+    }
+}
+
+/// Checksum of what `stream` produces in chunks of `chunk_len` bytes. The
+/// stream starts over before and after.
+pub fn stream_checksum(stream: &mut Stream, chunk_len: usize) -> u64 {
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; chunk_len];
+    stream.reset();
+    loop {
+        let len = stream.fill(&mut buf);
+        if len == 0 {
+            stream.reset();
+            return hasher.digest();
+        }
+        hasher.update(&buf[..len]);
+    }
+}
+
+pub fn verify(what: &str, expected: u64, actual: u64) -> Result<()> {
+    if expected == actual {
+        return Ok(());
+    }
+    Err(Error::Verification(format!(
+        "{} checksum mismatch: expected {:016x}, got {:016x}",
+        what, expected, actual
+    )))
+}
+
+/// Compares the checksum of the file at `path` with that of an input of
+/// `input_len` bytes, of which `written` bytes were written. A run stopped by
+/// its deadline before one whole pass cannot be verified.
+pub fn verify_file(path: &str, written: usize, input_len: usize, expected: u64) -> Result<()> {
+    if written < input_len {
+        return Err(Error::Config(format!(
+            "--verify needs a whole pass, but the deadline stopped it after {} of {} bytes",
+            written, input_len
+        )));
+    }
+    verify(path, expected, file_checksum(path)?)
+}
@@ -0,0 +1,157 @@
+use clap::Args;
+
+use crate::{
+    error::Result,
+    measure::welch_t_test,
+    result::{load_results, BenchResult},
+};
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Baseline result file (JSON).
+    baseline: String,
+    /// Candidate result file (JSON).
+    candidate: String,
+    /// Throughput drop in percent that counts as a regression.
+    #[clap(long, default_value_t = 5.0)]
+    threshold: f64,
+    /// Significance level of the t-test.
+    #[clap(long, default_value_t = 0.05)]
+    alpha: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Regression,
+    Improvement,
+    Unchanged,
+    // The baseline has no matching run.
+    New,
+    // The candidate has no matching run.
+    Missing,
+}
+
+pub struct Comparison<'a> {
+    pub baseline: Option<&'a BenchResult>,
+    pub candidate: Option<&'a BenchResult>,
+    // Relative change of the mean throughput in percent.
+    pub change: Option<f64>,
+    pub p_value: Option<f64>,
+    pub verdict: Verdict,
+}
+
+// Matches runs by benchmark and parameters and classifies each pair.
+pub fn compare<'a>(
+    baseline: &'a [BenchResult],
+    candidate: &'a [BenchResult],
+    threshold: f64,
+    alpha: f64,
+) -> Vec<Comparison<'a>> {
+    let same_run =
+        |a: &BenchResult, b: &BenchResult| a.benchmark == b.benchmark && a.parameters == b.parameters;
+    let mut comparisons = Vec::new();
+    for base in baseline {
+        let cand = match candidate.iter().find(|c| same_run(base, c)) {
+            Some(cand) => cand,
+            None => {
+                comparisons.push(Comparison {
+                    baseline: Some(base),
+                    candidate: None,
+                    change: None,
+                    p_value: None,
+                    verdict: Verdict::Missing,
+                });
+                continue;
+            }
+        };
+        let (b, c) = (&base.throughput, &cand.throughput);
+        let change = if b.mean != 0f64 {
+            Some((c.mean - b.mean) / b.mean * 100f64)
+        } else {
+            None
+        };
+        let p_value = welch_t_test(b, c);
+        // Without enough samples for a t-test the threshold alone decides.
+        let significant = match p_value {
+            Some(p) => p < alpha,
+            None => true,
+        };
+        let verdict = match change {
+            Some(change) if significant && change <= -threshold => Verdict::Regression,
+            Some(change) if significant && change >= threshold => Verdict::Improvement,
+            _ => Verdict::Unchanged,
+        };
+        comparisons.push(Comparison {
+            baseline: Some(base),
+            candidate: Some(cand),
+            change,
+            p_value,
+            verdict,
+        });
+    }
+    for cand in candidate {
+        if !baseline.iter().any(|b| same_run(b, cand)) {
+            comparisons.push(Comparison {
+                baseline: None,
+                candidate: Some(cand),
+                change: None,
+                p_value: None,
+                verdict: Verdict::New,
+            });
+        }
+    }
+    comparisons
+}
+
+fn fmt_opt(v: Option<f64>, precision: usize) -> String {
+    v.map(|v| format!("{:.*}", precision, v))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+// Prints the comparison table and returns whether any run regressed.
+pub fn run(args: &CompareArgs) -> Result<bool> {
+    let baseline = load_results(&args.baseline)?;
+    let candidate = load_results(&args.candidate)?;
+    let comparisons = compare(&baseline, &candidate, args.threshold, args.alpha);
+    println!(
+        "{:<20} {:<40} {:>14} {:>14} {:>9} {:>8}  verdict",
+        "benchmark", "parameters", "baseline(MB/s)", "candidate(MB/s)", "change%", "p"
+    );
+    let mut regressed = false;
+    for c in comparisons.iter() {
+        let run = c.baseline.or(c.candidate).unwrap();
+        let verdict = match c.verdict {
+            Verdict::Regression => "REGRESSION",
+            Verdict::Improvement => "improvement",
+            Verdict::Unchanged => "unchanged",
+            Verdict::New => "new",
+            Verdict::Missing => "missing",
+        };
+        regressed |= c.verdict == Verdict::Regression;
+        println!(
+            "{:<20} {:<40} {:>14} {:>14} {:>9} {:>8}  {}",
+            run.benchmark,
+            run.parameter_string(),
+            fmt_opt(c.baseline.map(|r| r.throughput.mean), 3),
+            fmt_opt(c.candidate.map(|r| r.throughput.mean), 3),
+            fmt_opt(c.change, 2),
+            fmt_opt(c.p_value, 4),
+            verdict
+        );
+    }
+    // Runs measured on different machines are not comparable as such.
+    let mut notes: Vec<String> = Vec::new();
+    for c in comparisons.iter() {
+        if let (Some(b), Some(c)) = (c.baseline, c.candidate) {
+            for diff in b.environment.differences(&c.environment) {
+                if !notes.contains(&diff) {
+                    notes.push(diff);
+                }
+            }
+        }
+    }
+    for note in notes {
+        println!("note: environment differs, {}", note);
+    }
+    Ok(regressed)
+}
@@ -7,30 +7,36 @@ use std::{
 use rand::Rng;
 use rdma_rs::ibv::{IbvMr, IbvQp};
 
-pub fn connect(stream: &mut TcpStream, qp: &IbvQp, my_lid: u16, buf: &[u8], mr: &IbvMr) -> MemInfo {
+use crate::error::{Error, Result};
+
+// Signal the client sends before it closes the stream.
+const DISCONNECT: i32 = 42;
+
+/// Exchanges queue pair and buffer information with the peer and moves `qp`
+/// to RTS. Returns the buffer of the peer.
+pub fn connect(
+    stream: &mut TcpStream,
+    qp: &IbvQp,
+    my_lid: u16,
+    buf: &[u8],
+    mr: &IbvMr,
+) -> Result<MemInfo> {
     // Send qpn, psn, lid.
-    qp.modify_reset2init(1).unwrap();
+    qp.modify_reset2init(1)
+        .map_err(|_| Error::last_verbs("ibv_modify_qp"))?;
     let my_qpn = qp.qpn();
     let my_psn = rand::thread_rng().gen::<u32>();
-    stream.write_all(&my_qpn.to_le_bytes()).unwrap();
-    stream.write_all(&my_psn.to_le_bytes()).unwrap();
-    stream.write_all(&my_lid.to_le_bytes()).unwrap();
-    stream.flush().unwrap();
+    stream.write_all(&my_qpn.to_le_bytes())?;
+    stream.write_all(&my_psn.to_le_bytes())?;
+    stream.write_all(&my_lid.to_le_bytes())?;
+    stream.flush()?;
     println!("my_qpn: {}, my_psn: {}, my_lid: {}", my_qpn, my_psn, my_lid);
     //Send addr, len, rkey.
-    stream
-        .write_all(&(buf.as_ptr() as u64).to_le_bytes())
-        .unwrap();
-    stream.write_all(&(buf.len() as u32).to_le_bytes()).unwrap();
-    stream.write_all(&mr.rkey().to_le_bytes()).unwrap();
-    let mut tcp_buf = [0u8; 1024];
-    let mut data_size = 26;
-    let mut meta_data: Vec<u8> = vec![];
-    while data_size > 0 {
-        let len = stream.read(&mut tcp_buf).unwrap();
-        meta_data.extend_from_slice(&tcp_buf[0..len]);
-        data_size -= len;
-    }
+    stream.write_all(&(buf.as_ptr() as u64).to_le_bytes())?;
+    stream.write_all(&(buf.len() as u32).to_le_bytes())?;
+    stream.write_all(&mr.rkey().to_le_bytes())?;
+    let mut meta_data = [0u8; 26];
+    stream.read_exact(&mut meta_data)?;
     // Get remote qpn, psn, lid.
     let remote_qpn = u32::from_le_bytes(meta_data[0..4].try_into().unwrap());
     let remote_psn = u32::from_le_bytes(meta_data[4..8].try_into().unwrap());
@@ -40,8 +46,9 @@ pub fn connect(stream: &mut TcpStream, qp: &IbvQp, my_lid: u16, buf: &[u8], mr:
         remote_qpn, remote_psn, remote_lid
     );
     qp.modify_init2rtr(0, 1, remote_qpn, remote_psn, remote_lid)
-        .unwrap();
-    qp.modify_rtr2rts(my_psn).unwrap();
+        .map_err(|_| Error::last_verbs("ibv_modify_qp"))?;
+    qp.modify_rtr2rts(my_psn)
+        .map_err(|_| Error::last_verbs("ibv_modify_qp"))?;
     // Get remote addr, len, rkey.
     
     let remote_addr = u64::from_le_bytes(meta_data[10..18].try_into().unwrap());
@@ -51,9 +58,10 @@ pub fn connect(stream: &mut TcpStream, qp: &IbvQp, my_lid: u16, buf: &[u8], mr:
         "remote_addr: {}, remote_len: {}, remote_rkey: {}",
         remote_addr, remote_len, remote_rkey
     );
-    MemInfo::new(remote_addr, remote_len, remote_rkey)
+    Ok(MemInfo::new(remote_addr, remote_len, remote_rkey))
 }
 
+/// A registered buffer of the peer: address, length and rkey.
 pub struct MemInfo {
     pub addr: u64,
     pub len: usize,
@@ -65,13 +73,18 @@ impl MemInfo {
     }
 }
 
-pub fn client_disconnect(mut stream: TcpStream) {
-    stream.write_all(&42_i32.to_le_bytes()).unwrap();
+pub fn client_disconnect(mut stream: TcpStream) -> Result<()> {
+    stream.write_all(&DISCONNECT.to_le_bytes())?;
     println!("client disconnect");
+    Ok(())
 }
-pub fn server_disconnect(mut stream: TcpStream) {
+pub fn server_disconnect(mut stream: TcpStream) -> Result<()> {
     let mut buf = [0u8; 4];
-    stream.read_exact(&mut buf).unwrap();
+    stream.read_exact(&mut buf)?;
     let signal = i32::from_le_bytes(buf);
     println!("disconnection signal {}", signal);
+    if signal != DISCONNECT {
+        return Err(Error::Protocol(format!("unexpected disconnection signal {}", signal)));
+    }
+    Ok(())
 }
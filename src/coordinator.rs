@@ -0,0 +1,314 @@
+//! Runs both sides of a benchmark pair from one command. An agent on every host
+//! listens on a control port and runs the benchmarks it is sent; the
+//! coordinator starts the server side on one agent and the client side on
+//! another, and collects both results.
+//!
+//! Every control connection carries one JSON line in each direction: the
+//! command line of the benchmark, then its result or error once it is done.
+//! Agents run each benchmark as a child process, with paths relative to the
+//! directory the agent was started in. Whoever can reach an agent can make it
+//! run any benchmark, e.g. overwrite files with disk-write, so agents listen on
+//! loopback unless they check a shared token or are told to trust the network.
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    process::{self, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bench::{Pair, Registry},
+    error::{Error, Result},
+    result::BenchResult,
+    sweep,
+};
+
+#[derive(Args, Debug)]
+pub struct AgentArgs {
+    /// Control address to listen on. Addresses other than loopback need
+    /// --token or --allow-remote.
+    #[clap(long, default_value = "127.0.0.1:9700")]
+    listen: String,
+    /// Only run benchmarks for coordinators that send this token.
+    #[clap(long)]
+    token: Option<String>,
+    /// Run benchmarks for anyone who can reach --listen, without a token.
+    #[clap(long)]
+    allow_remote: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CoordinateArgs {
+    /// Benchmark pair, see `list`.
+    pair: String,
+    /// Control address of the agent that runs the server side.
+    #[clap(long, default_value = "127.0.0.1:9700")]
+    server_agent: String,
+    /// Control address of the agent that runs the client side.
+    #[clap(long, default_value = "127.0.0.1:9700")]
+    client_agent: String,
+    /// Options of the server side, e.g. "--listen 0.0.0.0:9500".
+    #[clap(long, default_value = "", allow_hyphen_values = true)]
+    server_args: String,
+    /// Options of the client side, e.g. "--addr dl24:9500 --batch-size 1K,1M".
+    #[clap(long, default_value = "", allow_hyphen_values = true)]
+    client_args: String,
+    /// Options of both sides, e.g. "--repetitions 10".
+    #[clap(long, default_value = "", allow_hyphen_values = true)]
+    args: String,
+    /// Token the agents were started with.
+    #[clap(long)]
+    token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Request {
+    argv: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+// The result of the benchmark, or why it failed.
+type Response = std::result::Result<BenchResult, String>;
+
+// Runs started by this agent, to name their result files.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Serves control connections until the process is killed, each on its own
+/// thread so that one agent can run both sides of a pair.
+pub fn serve(registry: &Registry, args: &AgentArgs) -> Result<()> {
+    check_exposure(args)?;
+    let listener = TcpListener::bind(&args.listen)?;
+    println!("agent listening on {}", args.listen);
+    let registry = Arc::new(registry.clone());
+    let token = Arc::new(args.token.clone());
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept failed: {}", e);
+                continue;
+            }
+        };
+        let registry = registry.clone();
+        let token = token.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(&registry, token.as_deref(), stream) {
+                eprintln!("control connection failed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+// Refuses to listen beyond loopback without a token, unless asked to.
+fn check_exposure(args: &AgentArgs) -> Result<()> {
+    if args.token.is_some() || args.allow_remote {
+        return Ok(());
+    }
+    let mut addrs = args.listen.to_socket_addrs()?;
+    if addrs.all(|addr| addr.ip().is_loopback()) {
+        return Ok(());
+    }
+    Err(Error::Config(format!(
+        "anyone who can reach {} could run any benchmark, pass --token or --allow-remote",
+        args.listen
+    )))
+}
+
+fn handle(registry: &Registry, token: Option<&str>, mut stream: TcpStream) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let request: Request = serde_json::from_str(&line)
+        .map_err(|e| Error::Protocol(format!("bad request from {}: {}", peer, e)))?;
+    let response = if token.is_some() && request.token.as_deref() != token {
+        eprintln!("{} sent a wrong token", peer);
+        Err("wrong or missing --token".to_string())
+    } else {
+        eprintln!("{} asks for: {}", peer, request.argv.join(" "));
+        match run(registry, &request.argv, &stream) {
+            Ok(response) => response,
+            Err(e) => Err(e.to_string()),
+        }
+    };
+    if let Err(e) = &response {
+        eprintln!("failed: {}", e);
+    }
+    serde_json::to_writer(&mut stream, &response).map_err(io::Error::from)?;
+    stream.write_all(b"\n")?;
+    Ok(())
+}
+
+// Runs the benchmark in a child process, which is killed if the coordinator
+// goes away, e.g. because the other side of the pair failed and this side
+// would wait for it forever.
+fn run(registry: &Registry, argv: &[String], control: &TcpStream) -> Result<Response> {
+    let name = argv.first().map(String::as_str).unwrap_or_default();
+    let entry = match registry.get(name) {
+        Some(entry) => entry,
+        None => return Ok(Err(format!("unknown benchmark {}", name))),
+    };
+    if let Err(e) = entry.command().try_get_matches_from(argv) {
+        return Ok(Err(e.to_string()));
+    }
+    // Benchmarks print to stdout, so the result goes through a file.
+    let output = env::temp_dir().join(format!(
+        "benchmark-agent-{}-{}.json",
+        process::id(),
+        RUNS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut child = Command::new(env::current_exe()?)
+        .args(["--output", "json", "--output-file"])
+        .arg(&output)
+        .args(argv)
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let pid = child.id() as libc::pid_t;
+    let done = Arc::new(AtomicBool::new(false));
+    {
+        let control = control.try_clone()?;
+        let done = done.clone();
+        // The coordinator sends nothing more, reading only returns once it
+        // closes the connection.
+        thread::spawn(move || {
+            let _ = (&control).read(&mut [0u8; 1]);
+            if !done.load(Ordering::SeqCst) {
+                eprintln!("coordinator went away, killing {}", pid);
+                unsafe { libc::kill(pid, libc::SIGKILL) };
+            }
+        });
+    }
+    // Progress and errors go to the log of the agent, the last line is the
+    // error of a failed run.
+    let stderr = child.stderr.take().unwrap();
+    let log = thread::spawn(move || {
+        let mut last = String::new();
+        for line in BufReader::new(stderr).lines().map_while(|l| l.ok()) {
+            eprintln!("{}", line);
+            if !line.trim().is_empty() {
+                last = line;
+            }
+        }
+        last
+    });
+    let status = child.wait()?;
+    done.store(true, Ordering::SeqCst);
+    let last = log.join().unwrap_or_default();
+    let results = fs::read_to_string(&output);
+    let _ = fs::remove_file(&output);
+    if !status.success() {
+        return Ok(Err(if last.is_empty() {
+            format!("{} exited with {}", name, status)
+        } else {
+            last
+        }));
+    }
+    let results: Vec<BenchResult> = serde_json::from_str(&results?)
+        .map_err(|e| Error::Protocol(format!("bad output of {}: {}", name, e)))?;
+    match results.into_iter().next() {
+        Some(result) => Ok(Ok(result)),
+        None => Ok(Err(format!("{} recorded no result", name))),
+    }
+}
+
+/// Runs the pair once per sweep point and returns the server and the client
+/// result of every run, in that order. Only one side may sweep, unless both
+/// sweep over the same number of points.
+pub fn coordinate(registry: &Registry, args: &CoordinateArgs) -> Result<Vec<BenchResult>> {
+    let pair = registry
+        .pair(&args.pair)
+        .ok_or_else(|| Error::Config(format!("unknown benchmark pair {}", args.pair)))?;
+    let servers = command_lines(registry, pair.server, &args.server_args, &args.args)?;
+    let clients = command_lines(registry, pair.client, &args.client_args, &args.args)?;
+    if servers.len() > 1 && clients.len() > 1 && servers.len() != clients.len() {
+        return Err(Error::Config(format!(
+            "server sweeps over {} points and client over {}",
+            servers.len(),
+            clients.len()
+        )));
+    }
+    let runs = servers.len().max(clients.len());
+    let mut results = Vec::with_capacity(2 * runs);
+    for i in 0..runs {
+        let server = &servers[i.min(servers.len() - 1)];
+        let client = &clients[i.min(clients.len() - 1)];
+        eprintln!("[{}/{}] {}", i + 1, runs, describe(pair, server, client, args));
+        let (server, client) = run_pair(args, server, client)?;
+        results.push(server);
+        results.push(client);
+    }
+    Ok(results)
+}
+
+fn describe(pair: &Pair, server: &[String], client: &[String], args: &CoordinateArgs) -> String {
+    format!(
+        "{} on {}: {}; {} on {}: {}",
+        pair.server,
+        args.server_agent,
+        server[1..].join(" "),
+        pair.client,
+        args.client_agent,
+        client[1..].join(" ")
+    )
+}
+
+// The command lines of one side, checked before any agent is asked to run them.
+fn command_lines(registry: &Registry, name: &str, own: &str, shared: &str) -> Result<Vec<Vec<String>>> {
+    let entry = registry.get(name).unwrap();
+    let argv: Vec<String> = std::iter::once(name)
+        .chain(own.split_whitespace())
+        .chain(shared.split_whitespace())
+        .map(String::from)
+        .collect();
+    let variants = sweep::expand_args(&argv);
+    for variant in variants.iter() {
+        entry
+            .command()
+            .try_get_matches_from(variant)
+            .map_err(|e| Error::Config(e.to_string()))?;
+    }
+    Ok(variants)
+}
+
+// Starts the server before the client. Clients retry connecting, so the server
+// does not need to be listening yet. A server whose client failed may wait for
+// it forever, so a failed client is reported right away.
+fn run_pair(args: &CoordinateArgs, server: &[String], client: &[String]) -> Result<(BenchResult, BenchResult)> {
+    let server_stream = start(&args.server_agent, server, args)?;
+    let client_stream = start(&args.client_agent, client, args)?;
+    let client_result = finish(&args.client_agent, client_stream)?;
+    let server_result = finish(&args.server_agent, server_stream)?;
+    Ok((server_result, client_result))
+}
+
+fn start(agent: &str, argv: &[String], args: &CoordinateArgs) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect(agent)
+        .map_err(|e| io::Error::new(e.kind(), format!("agent {}: {}", agent, e)))?;
+    let request = Request {
+        argv: argv.to_vec(),
+        token: args.token.clone(),
+    };
+    serde_json::to_writer(&mut stream, &request).map_err(io::Error::from)?;
+    stream.write_all(b"\n")?;
+    Ok(stream)
+}
+
+fn finish(agent: &str, stream: TcpStream) -> Result<BenchResult> {
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    if line.is_empty() {
+        return Err(Error::Protocol(format!("agent {} closed the connection", agent)));
+    }
+    let response: Response = serde_json::from_str(&line)
+        .map_err(|e| Error::Protocol(format!("bad response from agent {}: {}", agent, e)))?;
+    response.map_err(|e| Error::Protocol(format!("agent {}: {}", agent, e)))
+}
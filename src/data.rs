@@ -0,0 +1,326 @@
+//! Synthetic input data, so that benchmarks do not depend on a prepared
+//! data/bigfile.log. The same pattern and seed always produce the same bytes.
+use std::{
+    cmp,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+};
+
+use clap::{ArgEnum, Args};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::sweep;
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+const DEFAULT_SIZE: usize = 1024 * 1024 * 1024;
+
+const WORDS: [&str; 32] = [
+    "the", "of", "and", "to", "in", "is", "that", "for", "it", "as", "was", "with", "be", "by",
+    "on", "not", "he", "this", "are", "or", "his", "from", "at", "which", "but", "have", "an",
+    "they", "you", "were", "her", "one",
+];
+const LEVELS: [&str; 4] = ["INFO ", "INFO ", "DEBUG", "WARN "];
+const METHODS: [&str; 3] = ["GET", "GET", "PUT"];
+const STATUS: [u16; 4] = [200, 200, 200, 404];
+
+#[derive(ArgEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// Zero bytes.
+    Zeros,
+    /// Incompressible pseudo random bytes.
+    Random,
+    /// Lines of words from a small vocabulary, compresses well.
+    Text,
+    /// Access-log like lines with timestamps and request fields.
+    Log,
+}
+
+pub struct Generator {
+    pattern: Pattern,
+    rng: StdRng,
+    line: Vec<u8>,
+    offset: usize,
+    millis: u64,
+}
+
+impl Generator {
+    pub fn new(pattern: Pattern, seed: u64) -> Self {
+        Self {
+            pattern,
+            rng: StdRng::seed_from_u64(seed),
+            line: Vec::new(),
+            offset: 0,
+            millis: 0,
+        }
+    }
+
+    /// Fills buf with the next bytes of the stream.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        match self.pattern {
+            Pattern::Zeros => buf.iter_mut().for_each(|b| *b = 0),
+            Pattern::Random => self.rng.fill_bytes(buf),
+            Pattern::Text | Pattern::Log => {
+                let mut n = 0;
+                while n < buf.len() {
+                    if self.offset == self.line.len() {
+                        self.next_line();
+                    }
+                    let len = cmp::min(buf.len() - n, self.line.len() - self.offset);
+                    buf[n..n + len].copy_from_slice(&self.line[self.offset..self.offset + len]);
+                    self.offset += len;
+                    n += len;
+                }
+            }
+        }
+    }
+
+    fn next_line(&mut self) {
+        self.line.clear();
+        self.offset = 0;
+        if self.pattern == Pattern::Text {
+            let words = self.rng.gen_range(4..16);
+            for i in 0..words {
+                if i > 0 {
+                    self.line.push(b' ');
+                }
+                let word = WORDS[self.rng.gen_range(0..WORDS.len())];
+                self.line.extend_from_slice(word.as_bytes());
+            }
+            self.line.extend_from_slice(b".\n");
+            return;
+        }
+        self.millis += self.rng.gen_range(0..50);
+        let secs = self.millis / 1000;
+        let _ = writeln!(
+            self.line,
+            "2022-01-{:02} {:02}:{:02}:{:02}.{:03} {} [worker-{:02}] {} /api/v1/objects/{} status={} bytes={} latency_us={}",
+            1 + secs / 86400 % 28,
+            secs / 3600 % 24,
+            secs / 60 % 60,
+            secs % 60,
+            self.millis % 1000,
+            LEVELS[self.rng.gen_range(0..LEVELS.len())],
+            self.rng.gen_range(0..16),
+            METHODS[self.rng.gen_range(0..METHODS.len())],
+            self.rng.gen_range(0..100_000),
+            STATUS[self.rng.gen_range(0..STATUS.len())],
+            self.rng.gen_range(0..65536),
+            self.rng.gen_range(10..5000),
+        );
+    }
+}
+
+/// How a streamed input is produced.
+#[derive(ArgEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamSource {
+    /// One chunk generated up front and repeated, generating costs nothing
+    /// while measuring.
+    Buffer,
+    /// Every chunk generated when it is needed, the input never repeats.
+    Generator,
+}
+
+/// A generated input of a fixed size, produced a chunk at a time instead of
+/// held in memory.
+pub struct Stream {
+    source: StreamSource,
+    pattern: Pattern,
+    seed: u64,
+    size: usize,
+    left: usize,
+    generator: Generator,
+    // Whether the buffer of the caller already holds the repeated chunk.
+    filled: bool,
+}
+
+impl Stream {
+    pub fn new(source: StreamSource, pattern: Pattern, size: usize, seed: u64) -> Self {
+        Self {
+            source,
+            pattern,
+            seed,
+            size,
+            left: size,
+            generator: Generator::new(pattern, seed),
+            filled: false,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Puts the next chunk into `buf` and returns its length, at most that of
+    /// `buf` and 0 at the end. With `StreamSource::Buffer` the chunk is only
+    /// generated on the first call, so `buf` must be the same on every call.
+    pub fn fill(&mut self, buf: &mut [u8]) -> usize {
+        let len = cmp::min(self.left, buf.len());
+        match self.source {
+            StreamSource::Buffer if self.filled => {}
+            StreamSource::Buffer => {
+                self.generator.fill(buf);
+                self.filled = true;
+            }
+            StreamSource::Generator => self.generator.fill(&mut buf[..len]),
+        }
+        self.left -= len;
+        len
+    }
+
+    /// Starts over with the same bytes.
+    pub fn rewind(&mut self) {
+        self.left = self.size;
+        if self.source == StreamSource::Generator {
+            self.generator = Generator::new(self.pattern, self.seed);
+        }
+    }
+
+    /// Forgets the repeated chunk, for a fill into a different buffer.
+    pub fn reset(&mut self) {
+        self.rewind();
+        self.generator = Generator::new(self.pattern, self.seed);
+        self.filled = false;
+    }
+}
+
+pub fn generate(size: usize, pattern: Pattern, seed: u64) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    Generator::new(pattern, seed).fill(&mut data);
+    data
+}
+
+pub fn write_file(path: &str, size: usize, pattern: Pattern, seed: u64) -> io::Result<()> {
+    let mut generator = Generator::new(pattern, seed);
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut chunk = vec![0u8; cmp::min(size, CHUNK_SIZE)];
+    let mut left = size;
+    while left > 0 {
+        let len = cmp::min(left, chunk.len());
+        generator.fill(&mut chunk[..len]);
+        out.write_all(&chunk[..len])?;
+        left -= len;
+    }
+    out.flush()
+}
+
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    sweep::parse_size(s)
+        .map(|v| v as usize)
+        .ok_or_else(|| format!("invalid size {}", s))
+}
+
+#[derive(Args, Debug)]
+pub struct GenDataArgs {
+    /// File to create.
+    path: String,
+    /// Size of the file, e.g. 512M.
+    #[clap(long, default_value = "1G", parse(try_from_str = parse_size))]
+    size: usize,
+    #[clap(long, arg_enum, default_value = "random")]
+    pattern: Pattern,
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+}
+
+impl GenDataArgs {
+    pub fn run(&self) -> io::Result<()> {
+        write_file(&self.path, self.size, self.pattern, self.seed)
+    }
+}
+
+/// Lets a benchmark generate its input instead of reading it from a file.
+#[derive(Args, Serialize, Debug)]
+pub struct InputArgs {
+    /// Generate the input with this pattern instead of using the file.
+    #[clap(long, arg_enum)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generate: Option<Pattern>,
+    /// Size of the generated input, 1G unless the benchmark has its own size.
+    #[clap(long, parse(try_from_str = parse_size))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_size: Option<usize>,
+    /// Seed of the generated input.
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+}
+
+impl InputArgs {
+    pub fn is_generated(&self) -> bool {
+        self.generate.is_some()
+    }
+
+    /// The input in memory: generated, or the content of path.
+    pub fn load(&self, path: &str, default_size: Option<usize>) -> io::Result<Vec<u8>> {
+        match self.generate {
+            Some(pattern) => Ok(generate(self.size(default_size), pattern, self.seed)),
+            None => {
+                let mut data = Vec::new();
+                File::open(path)?.read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    /// The generated input as a stream, random bytes unless --generate says
+    /// otherwise.
+    pub fn stream(&self, source: StreamSource, default_size: Option<usize>) -> Stream {
+        let pattern = self.generate.unwrap_or(Pattern::Random);
+        Stream::new(source, pattern, self.size(default_size), self.seed)
+    }
+
+    /// Makes sure path exists, writing the generated input to it if requested.
+    pub fn prepare_file(&self, path: &str, default_size: Option<usize>) -> io::Result<()> {
+        match self.generate {
+            Some(pattern) => write_file(path, self.size(default_size), pattern, self.seed),
+            None => File::open(path).map(|_| ()),
+        }
+    }
+
+    fn size(&self, default_size: Option<usize>) -> usize {
+        self.data_size.or(default_size).unwrap_or(DEFAULT_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("1.5M"), Err("invalid size 1.5M".to_string()));
+        assert!(parse_size("").is_err());
+    }
+
+    #[test]
+    fn generated_data_depends_only_on_the_seed() {
+        for pattern in [Pattern::Zeros, Pattern::Random, Pattern::Text, Pattern::Log] {
+            let data = generate(100_000, pattern, 7);
+            assert_eq!(data, generate(100_000, pattern, 7));
+            // Filling in pieces continues the same stream, random bytes only
+            // for pieces of whole words of the rng.
+            let mut generator = Generator::new(pattern, 7);
+            let mut pieces = vec![0u8; data.len()];
+            for chunk in pieces.chunks_mut(4096) {
+                generator.fill(chunk);
+            }
+            assert_eq!(pieces, data);
+        }
+        assert_ne!(generate(1000, Pattern::Random, 1), generate(1000, Pattern::Random, 2));
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(generate(1000, Pattern::Zeros, 0).iter().all(|b| *b == 0));
+        for pattern in [Pattern::Text, Pattern::Log] {
+            let data = generate(100_000, pattern, 0);
+            assert!(data.is_ascii());
+            assert!(data.iter().filter(|b| **b == b'\n').count() > 100);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! The page cache state a read round starts from. Without a policy, every
+//! round after the first reads a file that is already cached, and measures
+//! memory instead of the device.
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    os::unix::io::AsRawFd,
+    ptr,
+};
+
+use clap::ArgEnum;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(ArgEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CachePolicy {
+    /// Leave the cache as the previous round left it.
+    Keep,
+    /// Write back and evict the pages of the file with fsync and
+    /// posix_fadvise(POSIX_FADV_DONTNEED).
+    Cold,
+    /// Write back everything and empty the whole page cache through
+    /// /proc/sys/vm/drop_caches, needs root.
+    DropCaches,
+    /// Read the whole file, so that the round reads from memory.
+    Warm,
+}
+
+/// Puts the pages of `path` into the state `policy` asks for.
+pub fn apply(policy: CachePolicy, path: &str) -> Result<()> {
+    match policy {
+        CachePolicy::Keep => Ok(()),
+        CachePolicy::Cold => Ok(evict(path)?),
+        CachePolicy::DropCaches => drop_caches(),
+        CachePolicy::Warm => Ok(warm(path)?),
+    }
+}
+
+/// Rejects policies other than keep for several workers. Every worker
+/// prepares its own rounds, so evicting or warming the pages would change the
+/// cache under the reads of the others.
+pub fn check_workers(policy: CachePolicy, workers: usize) -> Result<()> {
+    if policy != CachePolicy::Keep && workers > 1 {
+        return Err(Error::Config(format!(
+            "--cache other than keep needs a single thread, {} workers would change the cache under each other's reads",
+            workers
+        )));
+    }
+    Ok(())
+}
+
+/// Evicts the pages of `path` from the page cache. Dirty pages are written
+/// back first, DONTNEED skips them.
+pub fn evict(path: &str) -> io::Result<()> {
+    let file = File::open(path)?;
+    file.sync_all()?;
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// Empties the page cache, dentries and inodes of the whole machine.
+pub fn drop_caches() -> Result<()> {
+    unsafe { libc::sync() };
+    fs::write("/proc/sys/vm/drop_caches", "3").map_err(|e| {
+        Error::Config(format!(
+            "cannot write /proc/sys/vm/drop_caches, --cache drop-caches needs root: {}",
+            e
+        ))
+    })
+}
+
+/// Reads the whole file into the page cache.
+pub fn warm(path: &str) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    while file.read(&mut buf)? > 0 {}
+    Ok(())
+}
+
+/// Fraction of the pages of `path` in the page cache, from mincore on a
+/// mapping of the file. Mapping it does not read it.
+pub fn residency(path: &str) -> io::Result<f64> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    if len == 0 {
+        return Ok(1f64);
+    }
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let addr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let mut pages = vec![0u8; len.div_ceil(page_size)];
+    let ret = unsafe { libc::mincore(addr, len, pages.as_mut_ptr()) };
+    let err = io::Error::last_os_error();
+    unsafe { libc::munmap(addr, len) };
+    if ret != 0 {
+        return Err(err);
+    }
+    let resident = pages.iter().filter(|p| **p & 1 != 0).count();
+    Ok(resident as f64 / pages.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_keep_with_several_workers() {
+        assert!(check_workers(CachePolicy::Keep, 4).is_ok());
+        for policy in [
+            CachePolicy::Cold,
+            CachePolicy::DropCaches,
+            CachePolicy::Warm,
+        ] {
+            assert!(check_workers(policy, 1).is_ok());
+            assert!(matches!(check_workers(policy, 2), Err(Error::Config(_))));
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! O_DIRECT IO, which bypasses the page cache. The file offset, the length and
+//! the buffer address of every call must be multiples of the logical block size
+//! of the device that holds the file, or the call fails with EINVAL.
+use std::{
+    alloc::{self, Layout},
+    fs::{self, File, OpenOptions},
+    io,
+    ops::{Deref, DerefMut},
+    os::unix::{
+        fs::{FileTypeExt, MetadataExt, OpenOptionsExt},
+        io::AsRawFd,
+    },
+    path::Path,
+    ptr::NonNull,
+    slice,
+};
+
+use crate::error::{Error, Result};
+
+// Used when the device of a file cannot be found, e.g. on network or overlay
+// file systems. No common device has a larger logical block.
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+// Buffers are at least page aligned, which every device accepts.
+const PAGE_SIZE: usize = 4096;
+// _IO(0x12, 104)
+const BLKSSZGET: libc::c_ulong = 0x1268;
+
+/// Opens `path` for reading, or creates or truncates it for writing, with
+/// O_DIRECT.
+pub fn open(path: &str, write: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    if write {
+        options.write(true).create(true).truncate(true);
+    } else {
+        options.read(true);
+    }
+    open_with(&mut options, 0, path)
+}
+
+/// Opens `path` with `options`, O_DIRECT and the open flags `flags`.
+pub fn open_with(options: &mut OpenOptions, flags: i32, path: &str) -> io::Result<File> {
+    options
+        .custom_flags(libc::O_DIRECT | flags)
+        .open(path)
+        .map_err(|e| {
+            if e.raw_os_error() == Some(libc::EINVAL) {
+                io::Error::new(
+                    e.kind(),
+                    format!("{}: the file system does not support O_DIRECT", path),
+                )
+            } else {
+                e
+            }
+        })
+}
+
+/// Logical block size of the device that holds `path`. A file that does not
+/// exist yet is looked up by its directory.
+pub fn logical_block_size(path: &str) -> usize {
+    let path = Path::new(path);
+    let metadata = fs::metadata(path).or_else(|_| {
+        match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            Some(dir) => fs::metadata(dir),
+            None => fs::metadata("."),
+        }
+    });
+    let metadata = match metadata {
+        Ok(metadata) => metadata,
+        Err(_) => return DEFAULT_BLOCK_SIZE,
+    };
+    if metadata.file_type().is_block_device() {
+        if let Some(size) = device_block_size(path) {
+            return size;
+        }
+    }
+    sysfs_block_size(metadata.dev()).unwrap_or(DEFAULT_BLOCK_SIZE)
+}
+
+/// Fails unless `len`, the value of `option`, is a multiple of the logical
+/// block size of `path`, and returns that size.
+pub fn check_aligned(path: &str, option: &str, len: usize) -> Result<usize> {
+    let block_size = logical_block_size(path);
+    if len == 0 || !len.is_multiple_of(block_size) {
+        return Err(Error::Config(format!(
+            "O_DIRECT needs {} to be a multiple of the logical block size of {}, {} bytes, not {}",
+            option, path, block_size, len
+        )));
+    }
+    Ok(block_size)
+}
+
+fn device_block_size(path: &Path) -> Option<usize> {
+    let file = File::open(path).ok()?;
+    let mut size: libc::c_int = 0;
+    match unsafe { libc::ioctl(file.as_raw_fd(), BLKSSZGET as _, &mut size) } {
+        0 if size > 0 => Some(size as usize),
+        _ => None,
+    }
+}
+
+// Regular files are looked up by the device number of their file system.
+// Partitions have no queue of their own, the disk they belong to does.
+fn sysfs_block_size(dev: u64) -> Option<usize> {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let base = format!("/sys/dev/block/{}:{}", major, minor);
+    ["queue", "../queue"]
+        .iter()
+        .filter_map(|queue| {
+            fs::read_to_string(format!("{}/{}/logical_block_size", base, queue)).ok()
+        })
+        .find_map(|size| size.trim().parse().ok())
+}
+
+/// A zeroed buffer whose address and capacity are multiples of `align` and of
+/// the page size. It derefs to its first `len` bytes, `padded` covers the
+/// whole capacity.
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+impl AlignedBuf {
+    pub fn new(len: usize, align: usize) -> Self {
+        let align = align.max(PAGE_SIZE);
+        let capacity = len.div_ceil(align).max(1) * align;
+        let layout = Layout::from_size_align(capacity, align).expect("alignment is a power of two");
+        let ptr = match NonNull::new(unsafe { alloc::alloc_zeroed(layout) }) {
+            Some(ptr) => ptr,
+            None => alloc::handle_alloc_error(layout),
+        };
+        Self { ptr, len, layout }
+    }
+
+    /// A copy of `data`, zero padded up to the capacity.
+    pub fn from_slice(data: &[u8], align: usize) -> Self {
+        let mut buf = Self::new(data.len(), align);
+        buf.copy_from_slice(data);
+        buf
+    }
+
+    pub fn padded(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+// The buffer owns its memory like a Vec<u8>.
+unsafe impl Send for AlignedBuf {}
@@ -0,0 +1,155 @@
+//! When written data is made durable. Without a sync, writes only reach the
+//! page cache and the measured bandwidth is that of memory.
+use std::{fs::File, io, os::unix::io::AsRawFd, time::Instant};
+
+use clap::{ArgEnum, Args};
+use serde::Serialize;
+
+use crate::{
+    data::parse_size,
+    error::{Error, Result},
+    histogram::Histogram,
+};
+
+#[derive(ArgEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncMode {
+    /// Leave the data in the page cache.
+    None,
+    /// fsync after every pass over the file, data and metadata.
+    Fsync,
+    /// fdatasync after every pass, metadata only as far as needed to read the data back.
+    Fdatasync,
+    /// Open the file with O_SYNC, every write waits for its data and metadata.
+    OSync,
+    /// Open the file with O_DSYNC, every write waits for its data.
+    ODsync,
+    /// sync_file_range over the data written since the last sync. It neither
+    /// writes metadata nor flushes the write cache of the device.
+    SyncFileRange,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct SyncArgs {
+    /// How written data is made durable.
+    #[clap(long, arg_enum, default_value = "none")]
+    sync: SyncMode,
+    /// With fsync, fdatasync or sync-file-range, also sync every this many
+    /// bytes, e.g. 1M.
+    #[clap(long, parse(try_from_str = parse_size))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sync_bytes: Option<usize>,
+    /// With fsync, fdatasync or sync-file-range, also sync every this many
+    /// write calls.
+    #[clap(long, conflicts_with = "sync-bytes")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sync_writes: Option<usize>,
+}
+
+impl SyncArgs {
+    pub fn check(&self) -> Result<()> {
+        let periodic = self.sync_bytes.is_some() || self.sync_writes.is_some();
+        let explicit = matches!(
+            self.sync,
+            SyncMode::Fsync | SyncMode::Fdatasync | SyncMode::SyncFileRange
+        );
+        if periodic && !explicit {
+            return Err(Error::Config(
+                "--sync-bytes and --sync-writes need --sync fsync, fdatasync or sync-file-range"
+                    .to_string(),
+            ));
+        }
+        if self.sync_bytes == Some(0) || self.sync_writes == Some(0) {
+            return Err(Error::Config("--sync-bytes and --sync-writes must not be 0".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Flags to open the output file with.
+    pub fn open_flags(&self) -> i32 {
+        match self.sync {
+            SyncMode::OSync => libc::O_SYNC,
+            SyncMode::ODsync => libc::O_DSYNC,
+            _ => 0,
+        }
+    }
+}
+
+/// Counts the writes of one pass over a file and syncs them as configured,
+/// timing every sync.
+pub struct Syncer {
+    mode: SyncMode,
+    every_bytes: Option<u64>,
+    every_writes: Option<usize>,
+    // File position after the last write, and up to where it was synced.
+    offset: u64,
+    synced: u64,
+    writes: usize,
+    pub latency: Histogram,
+}
+
+impl Syncer {
+    pub fn new(args: &SyncArgs) -> Self {
+        Self {
+            mode: args.sync,
+            every_bytes: args.sync_bytes.map(|b| b as u64),
+            every_writes: args.sync_writes,
+            offset: 0,
+            synced: 0,
+            writes: 0,
+            latency: Histogram::new(),
+        }
+    }
+
+    /// Counts a write of `len` bytes at the end of the previous one. True if a
+    /// periodic sync is due, which the caller does with `sync` once its
+    /// buffers are flushed.
+    pub fn wrote(&mut self, len: usize) -> bool {
+        self.offset += len as u64;
+        self.writes += 1;
+        match (self.every_bytes, self.every_writes) {
+            (Some(bytes), _) => self.offset - self.synced >= bytes,
+            (_, Some(writes)) => self.writes >= writes,
+            _ => false,
+        }
+    }
+
+    /// Syncs what was written since the last sync.
+    pub fn sync(&mut self, file: &File) -> io::Result<()> {
+        if self.offset == self.synced {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let ret = match self.mode {
+            SyncMode::Fsync => unsafe { libc::fsync(file.as_raw_fd()) },
+            SyncMode::Fdatasync => unsafe { libc::fdatasync(file.as_raw_fd()) },
+            SyncMode::SyncFileRange => unsafe {
+                libc::sync_file_range(
+                    file.as_raw_fd(),
+                    self.synced as libc::off64_t,
+                    (self.offset - self.synced) as libc::off64_t,
+                    libc::SYNC_FILE_RANGE_WAIT_BEFORE
+                        | libc::SYNC_FILE_RANGE_WRITE
+                        | libc::SYNC_FILE_RANGE_WAIT_AFTER,
+                )
+            },
+            // Writes were already durable, or are not meant to be.
+            SyncMode::None | SyncMode::OSync | SyncMode::ODsync => return Ok(()),
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.latency.record_duration(start.elapsed());
+        self.synced = self.offset;
+        self.writes = 0;
+        Ok(())
+    }
+
+    /// Starts the next pass at the beginning of the file, after the previous
+    /// one was synced.
+    pub fn rewind(&mut self) {
+        self.offset = 0;
+        self.synced = 0;
+        self.writes = 0;
+    }
+}
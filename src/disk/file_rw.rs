@@ -1,78 +1,867 @@
 use std::{
-    fs::File,
-    io::{BufReader, BufWriter, Read, Write},
-    time::{Duration, SystemTime},
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    time::{Instant, SystemTime},
 };
-pub fn read_throughput(in_path: &str, buf_len: usize) {
-    let round = 10;
+
+use clap::{ArgEnum, Args};
+use serde::Serialize;
+
+use crate::{
+    bench::{Benchmark, BenchmarkArgs},
+    checksum,
+    data::{parse_size, InputArgs, Stream, StreamSource},
+    disk::{
+        self,
+        cache::{self, CachePolicy},
+        direct::{self, AlignedBuf},
+        durability::{SyncArgs, Syncer},
+        uring::{EngineArgs, Op, Ring},
+    },
+    environment::Environment,
+    error::{Error, Result},
+    histogram::Histogram,
+    measure::Sample,
+    progress::Progress,
+};
+
+// Alignment of the parts of a file read by workers with --split ranges, at
+// least the logical block size of any device.
+const RANGE_ALIGN: u64 = 4096;
+
+/// Reads the whole file with `buf_len` bytes per `read` call.
+pub fn read_throughput(in_path: &str, buf_len: usize, progress: &Progress) -> Result<Sample> {
+    read_all(File::open(in_path)?, &mut vec![0u8; buf_len], progress)
+}
+
+/// Like `read_throughput`, through a `BufReader`.
+pub fn bufread_throughput(in_path: &str, buf_len: usize, progress: &Progress) -> Result<Sample> {
+    read_all(BufReader::new(File::open(in_path)?), &mut vec![0u8; buf_len], progress)
+}
+
+/// Like `read_throughput`, with O_DIRECT into an aligned buffer. `buf_len`
+/// must be a multiple of the logical block size of the file.
+pub fn direct_read_throughput(in_path: &str, buf_len: usize, progress: &Progress) -> Result<Sample> {
+    let block_size = direct::check_aligned(in_path, "--buf-len", buf_len)?;
+    let mut in_buf = AlignedBuf::new(buf_len, block_size);
+    read_all(direct::open(in_path, false)?, &mut in_buf, progress)
+}
+
+/// Reads `range` of `in_path`, or all of it, with up to the queue depth of
+/// `engine` reads in flight through io_uring, optionally with O_DIRECT.
+pub fn uring_read_throughput(
+    in_path: &str,
+    range: Option<Range<u64>>,
+    buf_len: usize,
+    direct: bool,
+    engine: &EngineArgs,
+    progress: &Progress,
+) -> Result<Sample> {
+    let (in_file, align) = if direct {
+        let block_size = direct::check_aligned(in_path, "--buf-len", buf_len)?;
+        (direct::open(in_path, false)?, block_size)
+    } else {
+        (File::open(in_path)?, 1)
+    };
+    let range = match range {
+        Some(range) => range,
+        None => 0..in_file.metadata()?.len(),
+    };
+    let mut ring = Ring::new(engine, in_file, buf_len, align)?;
+    let mut total_size = 0;
+    let mut latency = Histogram::new();
+    let start = SystemTime::now();
+    loop {
+        let mut offsets = range.clone().step_by(buf_len);
+        total_size += ring.run(
+            || {
+                offsets.next().map(|offset| Op {
+                    offset,
+                    len: buf_len.min(range_len(&range, offset, align)),
+                    write: false,
+                })
+            },
+            |_, _| {},
+            None,
+            &mut latency,
+            progress,
+        )?;
+        if progress.expired() || !progress.repeat() {
+            break;
+        }
+    }
+    let end = SystemTime::now();
+    Ok(Sample::new(total_size, end.duration_since(start).unwrap()).with_latency(latency))
+}
+
+/// Reads `range` of `in_path` with `pread` at offsets of its own, `buf_len`
+/// bytes per call, optionally with O_DIRECT.
+pub fn pread_throughput(
+    in_path: &str,
+    range: Range<u64>,
+    buf_len: usize,
+    direct: bool,
+    progress: &Progress,
+) -> Result<Sample> {
+    let (in_file, align) = if direct {
+        let block_size = direct::check_aligned(in_path, "--buf-len", buf_len)?;
+        (direct::open(in_path, false)?, block_size)
+    } else {
+        (File::open(in_path)?, 1)
+    };
+    let mut in_buf = AlignedBuf::new(buf_len, align);
     let mut total_size = 0;
-    let mut total_duration = 0f64;
-    for _i in 0..round {
-        let mut in_file = File::open(in_path).expect("Unable to open input file");
-        let mut in_buf = vec![0u8; buf_len];
-        let mut file_size = 0;
-        let read_start = SystemTime::now();
-        while let Ok(len) = in_file.read(&mut in_buf) {
-            file_size += len;
+    let mut latency = Histogram::new();
+    let start = SystemTime::now();
+    'passes: loop {
+        let mut offset = range.start;
+        while offset < range.end {
+            let len = buf_len.min(range_len(&range, offset, align));
+            let op_start = Instant::now();
+            let len = in_file.read_at(&mut in_buf[..len], offset)?;
+            latency.record_duration(op_start.elapsed());
             if len == 0 {
                 break;
             }
+            // The last read of a range can run into the next one.
+            let len = len.min((range.end - offset) as usize);
+            progress.record(len);
+            total_size += len;
+            offset += len as u64;
+            if progress.expired() {
+                break 'passes;
+            }
+        }
+        if !progress.repeat() {
+            break;
         }
-        let read_end = SystemTime::now();
-        let duration = read_end.duration_since(read_start).unwrap().as_secs_f64();
-        total_duration += duration;
-        total_size += file_size;
     }
-    let total_size = total_size as f64 / (1024f64 * 1024f64); //MB
-    let throughput = total_size / total_duration;
-    println!("read file throughput: {:.3}MB/s", throughput);
+    let end = SystemTime::now();
+    Ok(Sample::new(total_size, end.duration_since(start).unwrap()).with_latency(latency))
 }
 
-pub fn bufread_throughput(in_path: &str, buf_len: usize) {
-    let round = 10;
+// Bytes left in `range` from `offset`, rounded up to the alignment of O_DIRECT.
+// Reads past the end of the file come back short.
+fn range_len(range: &Range<u64>, offset: u64, align: usize) -> usize {
+    ((range.end - offset) as usize).next_multiple_of(align)
+}
+
+// Every read but the last at the end of the file is a full buffer, so the
+// offsets of O_DIRECT reads stay aligned.
+fn read_all<R: Read + Seek>(mut in_file: R, in_buf: &mut [u8], progress: &Progress) -> Result<Sample> {
+    let mut file_size = 0;
+    let mut latency = Histogram::new();
+    let read_start = SystemTime::now();
+    let mut op_start = Instant::now();
+    loop {
+        let len = in_file.read(in_buf)?;
+        latency.record_duration(op_start.elapsed());
+        file_size += len;
+        if len == 0 {
+            // time-bounded runs start over at the beginning of the file
+            if !progress.repeat() {
+                break;
+            }
+            in_file.seek(SeekFrom::Start(0))?;
+        } else {
+            progress.record(len);
+            if progress.expired() {
+                break;
+            }
+        }
+        op_start = Instant::now();
+    }
+    let read_end = SystemTime::now();
+    Ok(Sample::new(file_size, read_end.duration_since(read_start).unwrap()).with_latency(latency))
+}
+
+/// Writes `data` to `out_path` through a `BufWriter` of `writer_capacity`
+/// bytes, `buf_len` bytes per call, and makes it durable as `sync` says.
+/// Time-bounded runs overwrite the file from the start after every pass and
+/// stop in the middle of a pass once the deadline has passed.
+pub fn bufwrite_throughput(
+    data: &[u8],
+    out_path: &str,
+    buf_len: usize,
+    writer_capacity: usize,
+    sync: &SyncArgs,
+    progress: &Progress,
+) -> Result<Sample> {
     let mut total_size = 0;
-    let mut total_duration = 0f64;
-    for _i in 0..round {
-        let mut in_file = BufReader::new(File::open(in_path).expect("Unable to open input file"));
-        let mut in_buf = vec![0u8; buf_len];
-        let mut file_size = 0;
-        let read_start = SystemTime::now();
-        while let Ok(len) = in_file.read(&mut in_buf) {
-            file_size += len;
+    let mut out_file = BufWriter::with_capacity(writer_capacity, create(out_path, sync)?);
+    let mut syncer = Syncer::new(sync);
+    let mut latency = Histogram::new();
+    let start = SystemTime::now();
+    loop {
+        for chunk in data.chunks(buf_len) {
+            let op_start = Instant::now();
+            out_file.write_all(chunk)?;
+            latency.record_duration(op_start.elapsed());
+            progress.record(chunk.len());
+            total_size += chunk.len();
+            if syncer.wrote(chunk.len()) {
+                out_file.flush()?;
+                syncer.sync(out_file.get_ref())?;
+            }
+            if progress.expired() {
+                break;
+            }
+        }
+        out_file.flush()?;
+        syncer.sync(out_file.get_ref())?;
+        if progress.expired() || !progress.repeat() {
+            break;
+        }
+        out_file.seek(SeekFrom::Start(0))?;
+        syncer.rewind();
+    }
+    let end = SystemTime::now();
+    Ok(Sample::new(total_size, end.duration_since(start).unwrap())
+        .with_latency(latency)
+        .with_sync_latency(syncer.latency))
+}
+
+/// Like `bufwrite_throughput`, with the input produced by `stream` one chunk
+/// of `buf_len` bytes at a time, so that the file can be larger than memory.
+/// Generating the chunks counts towards the time, but not the write latency.
+pub fn stream_write_throughput(
+    stream: &mut Stream,
+    out_path: &str,
+    buf_len: usize,
+    writer_capacity: usize,
+    sync: &SyncArgs,
+    progress: &Progress,
+) -> Result<Sample> {
+    let mut total_size = 0;
+    let mut out_file = BufWriter::with_capacity(writer_capacity, create(out_path, sync)?);
+    let mut chunk = vec![0u8; buf_len];
+    // The repeated chunk of a buffer stream goes into this new buffer.
+    stream.reset();
+    let mut syncer = Syncer::new(sync);
+    let mut latency = Histogram::new();
+    let start = SystemTime::now();
+    loop {
+        stream.rewind();
+        loop {
+            let len = stream.fill(&mut chunk);
             if len == 0 {
                 break;
             }
+            let op_start = Instant::now();
+            out_file.write_all(&chunk[..len])?;
+            latency.record_duration(op_start.elapsed());
+            progress.record(len);
+            total_size += len;
+            if syncer.wrote(len) {
+                out_file.flush()?;
+                syncer.sync(out_file.get_ref())?;
+            }
+            if progress.expired() {
+                break;
+            }
+        }
+        out_file.flush()?;
+        syncer.sync(out_file.get_ref())?;
+        if progress.expired() || !progress.repeat() {
+            break;
+        }
+        out_file.seek(SeekFrom::Start(0))?;
+        syncer.rewind();
+    }
+    let end = SystemTime::now();
+    Ok(Sample::new(total_size, end.duration_since(start).unwrap())
+        .with_latency(latency)
+        .with_sync_latency(syncer.latency))
+}
+
+fn create(out_path: &str, sync: &SyncArgs) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(sync.open_flags())
+        .open(out_path)
+}
+
+/// Writes `data` to `out_path` with O_DIRECT, `buf_len` bytes per call.
+/// `buf_len` must be a multiple of the logical block size of the file. The
+/// padding of `data` is written along with the last block, and cut off again
+/// once the file is complete. A run stopped by its deadline in the first pass
+/// leaves the file as long as the blocks written.
+pub fn direct_write_throughput(
+    data: &AlignedBuf,
+    out_path: &str,
+    buf_len: usize,
+    sync: &SyncArgs,
+    progress: &Progress,
+) -> Result<Sample> {
+    direct::check_aligned(out_path, "--buf-len", buf_len)?;
+    let mut total_size = 0;
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    let mut out_file = direct::open_with(&mut options, sync.open_flags(), out_path)?;
+    let mut syncer = Syncer::new(sync);
+    let mut latency = Histogram::new();
+    let start = SystemTime::now();
+    loop {
+        for (i, chunk) in data.padded().chunks(buf_len).enumerate() {
+            let op_start = Instant::now();
+            out_file.write_all(chunk)?;
+            latency.record_duration(op_start.elapsed());
+            progress.record(chunk.len());
+            total_size += chunk.len().min(data.len() - i * buf_len);
+            if syncer.wrote(chunk.len()) {
+                syncer.sync(&out_file)?;
+            }
+            if progress.expired() {
+                break;
+            }
+        }
+        syncer.sync(&out_file)?;
+        if progress.expired() || !progress.repeat() {
+            break;
         }
-        let read_end = SystemTime::now();
-        let duration = read_end.duration_since(read_start).unwrap().as_secs_f64();
-        total_duration += duration;
-        total_size += file_size;
+        out_file.seek(SeekFrom::Start(0))?;
+        syncer.rewind();
     }
-    let total_size = total_size as f64 / (1024f64 * 1024f64); //MB
-    let throughput = total_size / total_duration;
-    println!("read file throughput: {:.3}MB/s", throughput);
+    out_file.set_len(total_size.min(data.len()) as u64)?;
+    let end = SystemTime::now();
+    Ok(Sample::new(total_size, end.duration_since(start).unwrap())
+        .with_latency(latency)
+        .with_sync_latency(syncer.latency))
 }
 
-pub fn bufwrite_throughput(in_path: &str, out_path: &str, buf_len: usize) {
-    let mut in_file = File::open(in_path).unwrap();
-    let mut data = Vec::new();
-    in_file.read_to_end(&mut data).unwrap();
-    let total_size = data.len();
-    let mut s = 0;
-    let mut out_file = BufWriter::new(File::create(out_path).unwrap());
+/// Writes `data` to `out_path` with up to the queue depth of `engine` writes
+/// in flight through io_uring, `buf_len` bytes each, and makes it durable as
+/// `sync` says. With `direct`, the last write is rounded up to a block and the
+/// file is cut to the length of `data` once it is complete.
+pub fn uring_write_throughput(
+    data: &[u8],
+    out_path: &str,
+    buf_len: usize,
+    direct: bool,
+    engine: &EngineArgs,
+    sync: &SyncArgs,
+    progress: &Progress,
+) -> Result<Sample> {
+    let (out_file, align) = if direct {
+        let block_size = direct::check_aligned(out_path, "--buf-len", buf_len)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        (direct::open_with(&mut options, sync.open_flags(), out_path)?, block_size)
+    } else {
+        (create(out_path, sync)?, 1)
+    };
+    let mut ring = Ring::new(engine, out_file, buf_len, align)?;
+    let mut syncer = Syncer::new(sync);
+    let mut total_size = 0;
+    let mut latency = Histogram::new();
     let start = SystemTime::now();
-    for _i in 0..(total_size / buf_len) {
-        out_file.write_all(&data[s..(s + buf_len)]).unwrap();
-        s += buf_len;
+    loop {
+        let mut offsets = (0..data.len()).step_by(buf_len);
+        // Time-bounded runs stop in the middle of a pass, the padding of
+        // O_DIRECT is not part of the input.
+        let written = ring.run(
+            || {
+                offsets.next().map(|offset| Op {
+                    offset: offset as u64,
+                    len: buf_len.min((data.len() - offset).next_multiple_of(align)),
+                    write: true,
+                })
+            },
+            |op, buf| {
+                let chunk = &data[op.offset as usize..];
+                let len = op.len.min(chunk.len());
+                buf[..len].copy_from_slice(&chunk[..len]);
+            },
+            Some(&mut syncer),
+            &mut latency,
+            progress,
+        )?;
+        syncer.sync(ring.file())?;
+        total_size += written.min(data.len());
+        if !progress.repeat() {
+            break;
+        }
+        syncer.rewind();
     }
-    if s < total_size {
-        out_file.write_all(&data[s..]).unwrap();
+    if direct {
+        ring.file().set_len(data.len() as u64)?;
     }
-    out_file.flush().unwrap();
     let end = SystemTime::now();
-    let duration = end.duration_since(start).unwrap().as_micros() as f64  / 1e6;
-    print!("total size: {}", total_size);
-    let total_size = total_size as f64 / (1024f64 * 1024f64);
-    let throughput = total_size / duration;
-    println!("rdma write throughput: {:.3}MB/s", throughput);
+    Ok(Sample::new(total_size, end.duration_since(start).unwrap())
+        .with_latency(latency)
+        .with_sync_latency(syncer.latency))
+}
+
+#[derive(ArgEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// Every worker reads the whole file.
+    Shared,
+    /// Worker i reads its own file `<path>.<i>`.
+    Files,
+    /// Worker i reads the i-th of equal parts of the file with pread.
+    Ranges,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct ReadArgs {
+    /// File to read.
+    #[clap(long, default_value = "data/bigfile.log")]
+    path: String,
+    /// Bytes read per call.
+    #[clap(long, default_value_t = 4096000)]
+    buf_len: usize,
+    /// Read with O_DIRECT, bypassing the page cache. --buf-len must be a
+    /// multiple of the logical block size of the file.
+    #[clap(long)]
+    direct: bool,
+    /// Page cache state every round starts from.
+    #[clap(long, arg_enum, default_value = "keep")]
+    cache: CachePolicy,
+    /// What every worker of --threads reads.
+    #[clap(long, arg_enum, default_value = "shared")]
+    split: Split,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    engine: EngineArgs,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    data: InputArgs,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct BufReadArgs {
+    #[clap(flatten)]
+    #[serde(flatten)]
+    read: ReadArgs,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct WriteArgs {
+    /// File whose content is written.
+    #[clap(long, default_value = "data/bigfile.log")]
+    input: String,
+    /// File to write.
+    #[clap(long, default_value = "log/bigfile.log")]
+    path: String,
+    /// Bytes written per call.
+    #[clap(long, default_value_t = 1024000)]
+    buf_len: usize,
+    /// Capacity of the BufWriter, e.g. 64K.
+    #[clap(long, default_value = "8K", parse(try_from_str = parse_size))]
+    writer_capacity: usize,
+    /// Generate the input while writing instead of loading it, --data-size
+    /// bytes of --generate content per pass: `buffer` repeats one chunk,
+    /// `generator` generates every chunk.
+    #[clap(long, arg_enum)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<StreamSource>,
+    /// Write with O_DIRECT instead of a BufWriter, bypassing the page cache.
+    /// --buf-len must be a multiple of the logical block size of the file.
+    #[clap(long)]
+    direct: bool,
+    /// Re-read the written file and compare its checksum with the input.
+    #[clap(long)]
+    verify: bool,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    sync: SyncArgs,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    engine: EngineArgs,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    data: InputArgs,
+}
+
+pub struct ReadBench {
+    args: ReadArgs,
+    buffered: bool,
+    // How much of the file was cached when the next round starts.
+    residency: Option<f64>,
+    // This worker and how many there are.
+    worker: usize,
+    workers: usize,
+    // Part of the file this worker reads, with --split ranges.
+    range: Option<Range<u64>>,
+}
+
+impl ReadBench {
+    // The part of the file of this worker. Parts start at multiples of
+    // RANGE_ALIGN, so that O_DIRECT reads stay aligned.
+    fn split_range(&self) -> Result<Range<u64>> {
+        let file_size = File::open(&self.args.path)?.metadata()?.len();
+        let part = file_size.div_ceil(self.workers as u64).next_multiple_of(RANGE_ALIGN);
+        let start = (self.worker as u64 * part).min(file_size);
+        let end = (start + part).min(file_size);
+        if start == end {
+            return Err(Error::Config(format!(
+                "{} is too small to split into {} ranges",
+                self.args.path, self.workers
+            )));
+        }
+        Ok(start..end)
+    }
+}
+
+impl Benchmark for ReadBench {
+    fn setup(&mut self) -> Result<()> {
+        disk::check_buf_len(self.args.buf_len)?;
+        if self.buffered && self.args.direct {
+            return Err(Error::Config(
+                "--direct reads around the BufReader, use disk-read".to_string(),
+            ));
+        }
+        self.args.engine.check()?;
+        cache::check_workers(self.args.cache, self.workers)?;
+        if self.buffered && self.args.engine.is_uring() {
+            return Err(Error::Config(
+                "--engine io-uring reads around the BufReader, use disk-read".to_string(),
+            ));
+        }
+        if self.buffered && self.args.split == Split::Ranges {
+            return Err(Error::Config(
+                "--split ranges reads with pread around the BufReader, use disk-read".to_string(),
+            ));
+        }
+        // Also with a single thread, which is not a worker of the pool.
+        if self.args.split == Split::Files {
+            self.args.path = format!("{}.{}", self.args.path, self.worker);
+        }
+        self.args.data.prepare_file(&self.args.path, None)?;
+        if self.args.direct {
+            direct::check_aligned(&self.args.path, "--buf-len", self.args.buf_len)?;
+        }
+        if self.args.split == Split::Ranges {
+            self.range = Some(self.split_range()?);
+        }
+        Ok(())
+    }
+    // Workers share the input file unless each generates its own. Setup picks
+    // the file of the worker with --split files.
+    fn set_worker(&mut self, worker: usize, workers: usize) {
+        if self.args.data.is_generated() && self.args.split != Split::Files {
+            self.args.path = format!("{}.{}", self.args.path, worker);
+        }
+        self.worker = worker;
+        self.workers = workers;
+    }
+    fn prepare(&mut self) -> Result<()> {
+        cache::apply(self.args.cache, &self.args.path)?;
+        self.residency = cache::residency(&self.args.path).ok();
+        Ok(())
+    }
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        let args = &self.args;
+        let sample = if self.buffered {
+            bufread_throughput(&self.args.path, self.args.buf_len, progress)
+        } else if args.engine.is_uring() {
+            let range = self.range.clone();
+            uring_read_throughput(&args.path, range, args.buf_len, args.direct, &args.engine, progress)
+        } else if let Some(range) = self.range.clone() {
+            pread_throughput(&args.path, range, args.buf_len, args.direct, progress)
+        } else if self.args.direct {
+            direct_read_throughput(&self.args.path, self.args.buf_len, progress)
+        } else {
+            read_throughput(&self.args.path, self.args.buf_len, progress)
+        }?;
+        Ok(sample.with_cache_residency(self.residency.take()))
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_path(&self.args.path);
+    }
+}
+
+pub struct WriteBench {
+    args: WriteArgs,
+    data: Vec<u8>,
+    // The input in an aligned buffer, for --direct.
+    aligned: Option<AlignedBuf>,
+    // The input produced while writing, for --stream.
+    stream: Option<Stream>,
+    checksum: u64,
+    // Bytes written by the last run, checked with --verify before the next
+    // iteration or at teardown, outside of the measurement.
+    written: Option<usize>,
+}
+
+impl WriteBench {
+    fn input_len(&self) -> usize {
+        match (&self.aligned, &self.stream) {
+            (Some(aligned), _) => aligned.len(),
+            (None, Some(stream)) => stream.size(),
+            (None, None) => self.data.len(),
+        }
+    }
+
+    fn verify(&mut self) -> Result<()> {
+        match self.written.take() {
+            Some(written) => {
+                checksum::verify_file(&self.args.path, written, self.input_len(), self.checksum)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Benchmark for WriteBench {
+    fn setup(&mut self) -> Result<()> {
+        disk::check_buf_len(self.args.buf_len)?;
+        self.args.sync.check()?;
+        self.args.engine.check()?;
+        if let Some(source) = self.args.stream {
+            if self.args.engine.is_uring() {
+                return Err(Error::Config(
+                    "--stream writes through the BufWriter and cannot be combined with --engine io-uring"
+                        .to_string(),
+                ));
+            }
+            if self.args.direct {
+                return Err(Error::Config(
+                    "--stream writes through the BufWriter and cannot be combined with --direct"
+                        .to_string(),
+                ));
+            }
+            let mut stream = self.args.data.stream(source, None);
+            if self.args.verify {
+                self.checksum = checksum::stream_checksum(&mut stream, self.args.buf_len);
+            }
+            self.stream = Some(stream);
+            return Ok(());
+        }
+        self.data = self.args.data.load(&self.args.input, None)?;
+        if self.args.verify {
+            self.checksum = checksum::checksum(&self.data);
+        }
+        if self.args.direct {
+            let block_size = direct::check_aligned(&self.args.path, "--buf-len", self.args.buf_len)?;
+            self.aligned = Some(AlignedBuf::from_slice(&self.data, block_size));
+            self.data = Vec::new();
+        }
+        Ok(())
+    }
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        let args = &self.args;
+        let sample = match (&self.aligned, self.stream.as_mut()) {
+            (aligned, _) if args.engine.is_uring() => {
+                let data = aligned.as_deref().unwrap_or(&self.data);
+                uring_write_throughput(
+                    data,
+                    &args.path,
+                    args.buf_len,
+                    args.direct,
+                    &args.engine,
+                    &args.sync,
+                    progress,
+                )?
+            }
+            (Some(data), _) => {
+                direct_write_throughput(data, &args.path, args.buf_len, &args.sync, progress)?
+            }
+            (None, Some(stream)) => stream_write_throughput(
+                stream,
+                &args.path,
+                args.buf_len,
+                args.writer_capacity,
+                &args.sync,
+                progress,
+            )?,
+            (None, None) => bufwrite_throughput(
+                &self.data,
+                &args.path,
+                args.buf_len,
+                args.writer_capacity,
+                &args.sync,
+                progress,
+            )?,
+        };
+        if self.args.verify {
+            self.written = Some(sample.bytes);
+        }
+        Ok(sample)
+    }
+    fn prepare(&mut self) -> Result<()> {
+        self.verify()
+    }
+    fn teardown(&mut self) -> Result<()> {
+        let res = self.verify();
+        self.data = Vec::new();
+        self.aligned = None;
+        self.stream = None;
+        res
+    }
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.path = format!("{}.{}", self.args.path, worker);
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_path(&self.args.input);
+        env.add_path(&self.args.path);
+    }
+}
+
+impl BenchmarkArgs for ReadArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(ReadBench {
+            args: self,
+            buffered: false,
+            residency: None,
+            worker: 0,
+            workers: 1,
+            range: None,
+        })
+    }
+}
+
+impl BenchmarkArgs for BufReadArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(ReadBench {
+            args: self.read,
+            buffered: true,
+            residency: None,
+            worker: 0,
+            workers: 1,
+            range: None,
+        })
+    }
+}
+
+impl BenchmarkArgs for WriteArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(WriteBench {
+            args: self,
+            data: Vec::new(),
+            aligned: None,
+            stream: None,
+            checksum: 0,
+            written: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Instant};
+
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[clap(flatten)]
+        engine: EngineArgs,
+        #[clap(flatten)]
+        sync: SyncArgs,
+    }
+
+    #[derive(Parser)]
+    struct ReadCli {
+        #[clap(flatten)]
+        args: ReadArgs,
+    }
+
+    // The bench of `worker` out of `workers`, as the pool sets it up.
+    fn read_bench(argv: &[&str], worker: usize, workers: usize) -> Result<ReadBench> {
+        let args = ReadCli::parse_from(["test"].iter().chain(argv)).args;
+        let mut bench = ReadBench {
+            args,
+            buffered: false,
+            residency: None,
+            worker: 0,
+            workers: 1,
+            range: None,
+        };
+        if workers > 1 {
+            bench.set_worker(worker, workers);
+        }
+        bench.setup()?;
+        Ok(bench)
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("benchmark-{}-{}", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    // Skips the test where io_uring is not allowed, e.g. in containers.
+    fn uring_write(data: &[u8], path: &str, progress: &Progress) -> Option<Sample> {
+        let cli = Cli::parse_from(["test", "--engine", "io-uring", "--queue-depth", "4"]);
+        match uring_write_throughput(data, path, 64 * 1024, false, &cli.engine, &cli.sync, progress) {
+            Err(Error::Config(msg)) if msg.starts_with("cannot set up io_uring") => None,
+            res => Some(res.unwrap()),
+        }
+    }
+
+    #[test]
+    fn uring_write_counts_written_bytes() {
+        let path = temp_path("uring-write");
+        let data = vec![7u8; 8 * 1024 * 1024];
+        if let Some(sample) = uring_write(&data, &path, &Progress::new()) {
+            assert_eq!(sample.bytes, data.len());
+            assert_eq!(fs::read(&path).unwrap(), data);
+        }
+        // An expired deadline stops after the writes in flight.
+        let progress = Progress::new().until(Instant::now());
+        if let Some(sample) = uring_write(&data, &path, &progress) {
+            assert!(sample.bytes <= 4 * 64 * 1024, "{} bytes", sample.bytes);
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn bufwrite_stops_at_the_deadline() {
+        let path = temp_path("bufwrite-deadline");
+        let cli = Cli::parse_from(["test"]);
+        let data = vec![7u8; 8 * 64 * 1024];
+        let progress = Progress::new().until(Instant::now());
+        let sample = bufwrite_throughput(&data, &path, 64 * 1024, 8192, &cli.sync, &progress).unwrap();
+        assert_eq!(sample.bytes, 64 * 1024);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 64 * 1024);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn ranges_cover_the_file() {
+        let path = temp_path("split-ranges");
+        let len = 10 * 4096 + 100;
+        fs::write(&path, vec![0u8; len as usize]).unwrap();
+        let ranges = |workers| -> Result<Vec<Range<u64>>> {
+            (0..workers)
+                .map(|worker| {
+                    let argv = ["--path", &path, "--split", "ranges"];
+                    Ok(read_bench(&argv, worker, workers)?.range.unwrap())
+                })
+                .collect()
+        };
+        let whole = ranges(1).unwrap();
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0], 0..len);
+        assert_eq!(
+            ranges(4).unwrap(),
+            [0..12288, 12288..24576, 24576..36864, 36864..len]
+        );
+        // Parts are at least one aligned block.
+        let parts = ranges(11).unwrap();
+        assert_eq!(parts[10], 40960..len);
+        assert!(parts.windows(2).all(|w| w[0].end == w[1].start));
+        assert!(matches!(ranges(12), Err(Error::Config(_))));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn files_also_split_for_one_thread() {
+        let path = temp_path("split-files");
+        fs::write(format!("{}.0", path), b"data").unwrap();
+        let bench = read_bench(&["--path", &path, "--split", "files"], 0, 1).unwrap();
+        assert_eq!(bench.args.path, format!("{}.0", path));
+        let bench = read_bench(&["--path", &path, "--split", "files"], 0, 2).unwrap();
+        assert_eq!(bench.args.path, format!("{}.0", path));
+        // Worker 1 has no file.
+        assert!(read_bench(&["--path", &path, "--split", "files"], 1, 2).is_err());
+        let _ = fs::remove_file(format!("{}.0", path));
+    }
 }
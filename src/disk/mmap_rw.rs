@@ -0,0 +1,434 @@
+//! Reads and writes through memory mappings, the way services use read-mostly
+//! index files. Pages are mapped on first access, so the minor and major page
+//! faults in the usage of a run are part of what it measures.
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::unix::io::AsRawFd,
+    ptr, slice,
+    time::{Instant, SystemTime},
+};
+
+use clap::{ArgEnum, Args};
+use serde::Serialize;
+
+use crate::{
+    bench::{Benchmark, BenchmarkArgs},
+    checksum,
+    data::InputArgs,
+    disk::{
+        self,
+        cache::{self, CachePolicy},
+    },
+    environment::Environment,
+    error::{Error, Result},
+    histogram::Histogram,
+    measure::Sample,
+    progress::Progress,
+};
+
+#[derive(ArgEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Advice {
+    /// No madvise call.
+    None,
+    /// MADV_SEQUENTIAL: aggressive readahead, pages can be dropped once read.
+    Sequential,
+    /// MADV_WILLNEED: start reading the whole mapping right away.
+    Willneed,
+    /// MADV_HUGEPAGE: back the mapping with transparent huge pages where the
+    /// file system supports them.
+    Hugepage,
+}
+
+#[derive(ArgEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// Copy every chunk into a buffer, the same work as a `read` call.
+    Copy,
+    /// Read one byte per page, only the cost of mapping the pages.
+    Touch,
+}
+
+#[derive(ArgEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Msync {
+    /// Leave the dirty pages to the kernel.
+    None,
+    /// MS_ASYNC: schedule the write back and return.
+    Async,
+    /// MS_SYNC: wait until the pages are written back.
+    Sync,
+}
+
+/// A shared mapping of a whole file, unmapped on drop.
+pub struct Mapping {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapping {
+    pub fn new(file: &File, len: usize, writable: bool) -> io::Result<Self> {
+        let prot = if writable {
+            libc::PROT_READ | libc::PROT_WRITE
+        } else {
+            libc::PROT_READ
+        };
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                prot,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { addr, len })
+    }
+
+    pub fn advise(&self, advice: Advice) -> io::Result<()> {
+        let advice = match advice {
+            Advice::None => return Ok(()),
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Willneed => libc::MADV_WILLNEED,
+            Advice::Hugepage => libc::MADV_HUGEPAGE,
+        };
+        match unsafe { libc::madvise(self.addr, self.len, advice) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Writes back `len` bytes from `offset`, which is rounded down to a page.
+    pub fn sync(&self, offset: usize, len: usize, mode: Msync) -> io::Result<()> {
+        let flags = match mode {
+            Msync::None => return Ok(()),
+            Msync::Async => libc::MS_ASYNC,
+            Msync::Sync => libc::MS_SYNC,
+        };
+        let start = offset - offset % page_size();
+        let ret = unsafe {
+            libc::msync(
+                (self.addr as *mut u8).add(start) as *mut libc::c_void,
+                offset + len - start,
+                flags,
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.addr as *const u8, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.addr as *mut u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.len) };
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Maps `in_path` and reads it front to back, `buf_len` bytes per step. The
+/// mapping is made anew for every call, so every page faults in again.
+pub fn mmap_read_throughput(
+    in_path: &str,
+    buf_len: usize,
+    advice: Advice,
+    access: Access,
+    progress: &Progress,
+) -> Result<Sample> {
+    let start = SystemTime::now();
+    let file = File::open(in_path)?;
+    let len = file.metadata()?.len() as usize;
+    if len == 0 {
+        return Err(Error::Config(format!(
+            "cannot map the empty file {}",
+            in_path
+        )));
+    }
+    let mapping = Mapping::new(&file, len, false)?;
+    mapping.advise(advice)?;
+    let data = mapping.as_slice();
+    let mut buf = vec![0u8; buf_len];
+    let step = page_size();
+    let mut sum = 0u8;
+    let mut total = 0;
+    let mut latency = Histogram::new();
+    'passes: loop {
+        for chunk in data.chunks(buf_len) {
+            let op_start = Instant::now();
+            match access {
+                Access::Copy => buf[..chunk.len()].copy_from_slice(chunk),
+                Access::Touch => {
+                    for i in (0..chunk.len()).step_by(step) {
+                        sum = sum.wrapping_add(unsafe { ptr::read_volatile(&chunk[i]) });
+                    }
+                }
+            }
+            latency.record_duration(op_start.elapsed());
+            progress.record(chunk.len());
+            total += chunk.len();
+            if progress.expired() {
+                break 'passes;
+            }
+        }
+        if !progress.repeat() {
+            break;
+        }
+    }
+    // Keep the touched bytes alive.
+    std::hint::black_box((sum, &buf));
+    drop(mapping);
+    let end = SystemTime::now();
+    Ok(Sample::new(total, end.duration_since(start).unwrap()).with_latency(latency))
+}
+
+/// Copies `data` into a shared mapping of `out_path`, `buf_len` bytes per
+/// step, and writes the dirty pages back with msync every `msync_bytes` and at
+/// the end of every pass. Time-bounded runs stop in the middle of a pass once
+/// the deadline has passed.
+pub fn mmap_write_throughput(
+    data: &[u8],
+    out_path: &str,
+    buf_len: usize,
+    msync: Msync,
+    msync_bytes: Option<usize>,
+    progress: &Progress,
+) -> Result<Sample> {
+    if data.is_empty() {
+        return Err(Error::Config("cannot map an empty output".to_string()));
+    }
+    let start = SystemTime::now();
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(out_path)?;
+    file.set_len(data.len() as u64)?;
+    let mut mapping = Mapping::new(&file, data.len(), true)?;
+    let mut total = 0;
+    let mut latency = Histogram::new();
+    let mut sync_latency = Histogram::new();
+    loop {
+        let mut offset = 0;
+        let mut synced = 0;
+        for chunk in data.chunks(buf_len) {
+            let op_start = Instant::now();
+            mapping.as_mut_slice()[offset..offset + chunk.len()].copy_from_slice(chunk);
+            latency.record_duration(op_start.elapsed());
+            progress.record(chunk.len());
+            offset += chunk.len();
+            total += chunk.len();
+            if msync_bytes.is_some_and(|bytes| offset - synced >= bytes) {
+                let sync_start = Instant::now();
+                mapping.sync(synced, offset - synced, msync)?;
+                sync_latency.record_duration(sync_start.elapsed());
+                synced = offset;
+            }
+            if progress.expired() {
+                break;
+            }
+        }
+        if synced < offset && msync != Msync::None {
+            let sync_start = Instant::now();
+            mapping.sync(synced, offset - synced, msync)?;
+            sync_latency.record_duration(sync_start.elapsed());
+        }
+        if progress.expired() || !progress.repeat() {
+            break;
+        }
+    }
+    drop(mapping);
+    let end = SystemTime::now();
+    Ok(Sample::new(total, end.duration_since(start).unwrap())
+        .with_latency(latency)
+        .with_sync_latency(sync_latency))
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct MmapReadArgs {
+    /// File to map and read.
+    #[clap(long, default_value = "data/bigfile.log")]
+    path: String,
+    /// Bytes read per step.
+    #[clap(long, default_value_t = 4096000)]
+    buf_len: usize,
+    #[clap(long, arg_enum, default_value = "none")]
+    advice: Advice,
+    #[clap(long, arg_enum, default_value = "copy")]
+    access: Access,
+    /// Page cache state every round starts from.
+    #[clap(long, arg_enum, default_value = "keep")]
+    cache: CachePolicy,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    data: InputArgs,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct MmapWriteArgs {
+    /// File whose content is written.
+    #[clap(long, default_value = "data/bigfile.log")]
+    input: String,
+    /// File to map and write.
+    #[clap(long, default_value = "log/bigfile.log")]
+    path: String,
+    /// Bytes copied into the mapping per step.
+    #[clap(long, default_value_t = 1024000)]
+    buf_len: usize,
+    /// How dirty pages are written back at the end of every pass.
+    #[clap(long, arg_enum, default_value = "sync")]
+    msync: Msync,
+    /// Also msync every this many bytes, e.g. 4M.
+    #[clap(long, parse(try_from_str = crate::data::parse_size))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msync_bytes: Option<usize>,
+    /// Re-read the written file and compare its checksum with the input.
+    #[clap(long)]
+    verify: bool,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    data: InputArgs,
+}
+
+pub struct MmapReadBench {
+    args: MmapReadArgs,
+    residency: Option<f64>,
+    workers: usize,
+}
+
+impl Benchmark for MmapReadBench {
+    fn setup(&mut self) -> Result<()> {
+        disk::check_buf_len(self.args.buf_len)?;
+        cache::check_workers(self.args.cache, self.workers)?;
+        self.args.data.prepare_file(&self.args.path, None)?;
+        Ok(())
+    }
+    fn prepare(&mut self) -> Result<()> {
+        cache::apply(self.args.cache, &self.args.path)?;
+        self.residency = cache::residency(&self.args.path).ok();
+        Ok(())
+    }
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        let args = &self.args;
+        let sample =
+            mmap_read_throughput(&args.path, args.buf_len, args.advice, args.access, progress)?;
+        Ok(sample.with_cache_residency(self.residency.take()))
+    }
+    // Workers share the input file unless each generates its own.
+    fn set_worker(&mut self, worker: usize, workers: usize) {
+        if self.args.data.is_generated() {
+            self.args.path = format!("{}.{}", self.args.path, worker);
+        }
+        self.workers = workers;
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_path(&self.args.path);
+    }
+}
+
+pub struct MmapWriteBench {
+    args: MmapWriteArgs,
+    data: Vec<u8>,
+    checksum: u64,
+    // Bytes written by the last run, checked with --verify before the next
+    // iteration or at teardown, outside of the measurement.
+    written: Option<usize>,
+}
+
+impl MmapWriteBench {
+    fn verify(&mut self) -> Result<()> {
+        match self.written.take() {
+            Some(written) => {
+                checksum::verify_file(&self.args.path, written, self.data.len(), self.checksum)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Benchmark for MmapWriteBench {
+    fn setup(&mut self) -> Result<()> {
+        disk::check_buf_len(self.args.buf_len)?;
+        if self.args.msync_bytes.is_some() && self.args.msync == Msync::None {
+            return Err(Error::Config(
+                "--msync-bytes needs --msync async or sync".to_string(),
+            ));
+        }
+        if self.args.msync_bytes == Some(0) {
+            return Err(Error::Config("--msync-bytes must not be 0".to_string()));
+        }
+        self.data = self.args.data.load(&self.args.input, None)?;
+        if self.args.verify {
+            self.checksum = checksum::checksum(&self.data);
+        }
+        Ok(())
+    }
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        let args = &self.args;
+        let sample = mmap_write_throughput(
+            &self.data,
+            &args.path,
+            args.buf_len,
+            args.msync,
+            args.msync_bytes,
+            progress,
+        )?;
+        if args.verify {
+            self.written = Some(sample.bytes);
+        }
+        Ok(sample)
+    }
+    fn prepare(&mut self) -> Result<()> {
+        self.verify()
+    }
+    fn teardown(&mut self) -> Result<()> {
+        let res = self.verify();
+        self.data = Vec::new();
+        res
+    }
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.path = format!("{}.{}", self.args.path, worker);
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_path(&self.args.input);
+        env.add_path(&self.args.path);
+    }
+}
+
+impl BenchmarkArgs for MmapReadArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(MmapReadBench {
+            args: self,
+            residency: None,
+            workers: 1,
+        })
+    }
+}
+
+impl BenchmarkArgs for MmapWriteArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(MmapWriteBench {
+            args: self,
+            data: Vec::new(),
+            checksum: 0,
+            written: None,
+        })
+    }
+}
@@ -1 +1,30 @@
-pub mod file_rw;
\ No newline at end of file
+//! File read and write workloads.
+use crate::{
+    bench::Registry,
+    error::{Error, Result},
+};
+
+pub mod cache;
+pub mod direct;
+pub mod durability;
+pub mod file_rw;
+pub mod mmap_rw;
+pub mod random_io;
+pub mod uring;
+
+pub fn register(registry: &mut Registry) {
+    registry.register::<file_rw::ReadArgs>("disk-read", "Sequential file read with File::read");
+    registry.register::<file_rw::BufReadArgs>("disk-bufread", "Sequential file read with BufReader");
+    registry.register::<file_rw::WriteArgs>("disk-write", "Sequential file write with BufWriter");
+    registry.register::<mmap_rw::MmapReadArgs>("disk-mmap-read", "Sequential read through a read-only mapping");
+    registry.register::<mmap_rw::MmapWriteArgs>("disk-mmap-write", "Sequential write through a shared mapping and msync");
+    registry.register::<random_io::RandomArgs>("disk-random", "Block reads and writes at random offsets");
+}
+
+/// Rejects a --buf-len of 0, which would never get through the file.
+pub fn check_buf_len(buf_len: usize) -> Result<()> {
+    if buf_len == 0 {
+        return Err(Error::Config("--buf-len must not be 0".to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,483 @@
+//! Block reads and writes at chosen offsets of one file with `pread` and
+//! `pwrite`, the access pattern of databases, reported as IOPS and latency.
+use std::{
+    fs::{self, File, OpenOptions},
+    os::unix::fs::FileExt,
+    sync::Mutex,
+    time::Instant,
+};
+
+use clap::{ArgEnum, Args};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::{
+    bench::{Benchmark, BenchmarkArgs},
+    data::{self, parse_size, Pattern},
+    disk::{
+        direct::{self, AlignedBuf},
+        uring::{EngineArgs, Op, Ring},
+    },
+    environment::Environment,
+    error::{Error, Result},
+    histogram::Histogram,
+    measure::Sample,
+    progress::Progress,
+};
+
+#[derive(ArgEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Distribution {
+    /// Every block equally likely.
+    Uniform,
+    /// A few hot blocks get most accesses, spread over the file.
+    Zipfian,
+    /// Consecutive offsets `stride` apart, wrapping around at the end.
+    Sequential,
+}
+
+/// Seeded block offsets of a file following a distribution. Offsets are
+/// multiples of the block size, except for sequential ones with a stride that
+/// is not.
+pub struct Offsets {
+    distribution: Distribution,
+    file_size: u64,
+    block_size: u64,
+    blocks: u64,
+    stride: u64,
+    rng: StdRng,
+    zipfian: Option<Zipfian>,
+    // Next and first offset of the current sequential pass.
+    next: u64,
+    start: u64,
+}
+
+impl Offsets {
+    /// `theta` is the skew of the zipfian distribution, in (0, 1).
+    pub fn new(
+        distribution: Distribution,
+        file_size: u64,
+        block_size: u64,
+        stride: u64,
+        theta: f64,
+        seed: u64,
+    ) -> Self {
+        let blocks = (file_size / block_size).max(1);
+        let zipfian = match distribution {
+            Distribution::Zipfian => Some(Zipfian::new(blocks, theta)),
+            _ => None,
+        };
+        Self {
+            distribution,
+            file_size,
+            block_size,
+            blocks,
+            stride,
+            rng: StdRng::seed_from_u64(seed),
+            zipfian,
+            next: 0,
+            start: 0,
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+}
+
+impl Iterator for Offsets {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let offset = match self.distribution {
+            Distribution::Uniform => self.rng.gen_range(0..self.blocks) * self.block_size,
+            Distribution::Zipfian => {
+                let rank = self.zipfian.as_ref().unwrap().sample(&mut self.rng);
+                // Without scattering, the hottest blocks would be the first
+                // ones of the file and share cache lines and readahead.
+                fnv1a(rank) % self.blocks * self.block_size
+            }
+            Distribution::Sequential => {
+                let offset = self.next;
+                self.next += self.stride;
+                if self.next + self.block_size > self.file_size {
+                    // Strided passes start one block later each time, so that
+                    // they visit the blocks in between.
+                    if self.stride > self.block_size {
+                        self.start = (self.start + self.block_size) % self.stride;
+                    }
+                    if self.start + self.block_size > self.file_size {
+                        self.start = 0;
+                    }
+                    self.next = self.start;
+                }
+                offset
+            }
+        };
+        Some(offset)
+    }
+}
+
+// Zipfian ranks in [0, n) as generated by YCSB (Gray et al., "Quickly
+// generating billion-record synthetic databases"), rank 0 is the most popular.
+struct Zipfian {
+    n: f64,
+    theta: f64,
+    zetan: f64,
+    alpha: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    fn new(n: u64, theta: f64) -> Self {
+        let zetan = zeta(n, theta);
+        let zeta2 = zeta(2.min(n), theta);
+        let n = n as f64;
+        Self {
+            n,
+            theta,
+            zetan,
+            alpha: 1f64 / (1f64 - theta),
+            eta: (1f64 - (2f64 / n).powf(1f64 - theta)) / (1f64 - zeta2 / zetan),
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> u64 {
+        let u: f64 = rng.gen();
+        let uz = u * self.zetan;
+        if uz < 1f64 {
+            return 0;
+        }
+        if uz < 1f64 + 0.5f64.powf(self.theta) {
+            return 1;
+        }
+        let rank = (self.n * (self.eta * u - self.eta + 1f64).powf(self.alpha)) as u64;
+        rank.min(self.n as u64 - 1)
+    }
+}
+
+// Terms of zeta summed one by one, the rest of a longer sum is approximated.
+const ZETA_TERMS: u64 = 1024;
+
+// Sum of 1 / i^theta for i in [1, n]. Beyond ZETA_TERMS the Euler-Maclaurin
+// formula replaces the sum, so that files of many blocks do not take O(n)
+// per worker, it agrees with the sum to the precision of f64.
+fn zeta(n: u64, theta: f64) -> f64 {
+    let sum = |n: u64| (1..=n).map(|i| (i as f64).powf(-theta)).sum::<f64>();
+    if n <= ZETA_TERMS {
+        return sum(n);
+    }
+    let f = |x: f64| x.powf(-theta);
+    let df = |x: f64| -theta * x.powf(-theta - 1f64);
+    let (m, n) = (ZETA_TERMS as f64, n as f64);
+    sum(ZETA_TERMS)
+        + (n.powf(1f64 - theta) - m.powf(1f64 - theta)) / (1f64 - theta)
+        + (f(n) - f(m)) / 2f64
+        + (df(n) - df(m)) / 12f64
+}
+
+fn fnv1a(value: u64) -> u64 {
+    value
+        .to_le_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+            (hash ^ *b as u64).wrapping_mul(0x100_0000_01b3)
+        })
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct RandomArgs {
+    /// File to access. It is first filled with --file-size random bytes if it
+    /// is missing or shorter.
+    #[clap(long, default_value = "log/random.dat")]
+    path: String,
+    /// Size of the accessed part of the file.
+    #[clap(long, default_value = "1G", parse(try_from_str = parse_size))]
+    file_size: usize,
+    /// Bytes per read or write, e.g. 4K.
+    #[clap(long, default_value = "4K", parse(try_from_str = parse_size))]
+    block_size: usize,
+    #[clap(long, arg_enum, default_value = "uniform")]
+    distribution: Distribution,
+    /// Skew of the zipfian distribution, between 0 and 1.
+    #[clap(long, default_value_t = 0.99)]
+    theta: f64,
+    /// Distance between sequential offsets, one block by default.
+    #[clap(long, parse(try_from_str = parse_size))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stride: Option<usize>,
+    /// Percentage of operations that read, the others write.
+    #[clap(long, default_value_t = 100)]
+    read_percent: u32,
+    /// Operations per repetition.
+    #[clap(long, default_value_t = 100000)]
+    ops: usize,
+    /// Seed of the offsets and of the choice between read and write.
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+    /// Open the file with O_DIRECT, bypassing the page cache. --block-size and
+    /// --stride must be multiples of the logical block size of the file.
+    #[clap(long)]
+    direct: bool,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    engine: EngineArgs,
+}
+
+// Held while a worker checks and fills the file, so that workers of a pool
+// sharing it do not all write it at once.
+static CREATE: Mutex<()> = Mutex::new(());
+
+pub struct RandomBench {
+    args: RandomArgs,
+    file: Option<File>,
+    offsets: Option<Offsets>,
+    buf: Option<AlignedBuf>,
+    // Owns the file and the buffers instead, with --engine io-uring.
+    ring: Option<Ring>,
+}
+
+impl RandomBench {
+    fn check(&self) -> Result<()> {
+        let args = &self.args;
+        if args.block_size == 0 || args.block_size > args.file_size {
+            return Err(Error::Config(format!(
+                "--block-size {} must be between 1 and --file-size {}",
+                args.block_size, args.file_size
+            )));
+        }
+        if args.read_percent > 100 {
+            return Err(Error::Config(format!(
+                "--read-percent {} is over 100",
+                args.read_percent
+            )));
+        }
+        if args.distribution == Distribution::Zipfian && !(args.theta > 0f64 && args.theta < 1f64) {
+            return Err(Error::Config(format!(
+                "--theta {} is not between 0 and 1",
+                args.theta
+            )));
+        }
+        if args.stride == Some(0) {
+            return Err(Error::Config("--stride must not be 0".to_string()));
+        }
+        Ok(())
+    }
+
+    // Like `run`, with the queue depth of --engine io-uring in flight. The
+    // buffers keep the random bytes, reads and writes pick the offsets in the
+    // same order.
+    fn run_uring(&mut self, progress: &Progress) -> Result<Sample> {
+        let ring = self.ring.as_mut().unwrap();
+        let offsets = self.offsets.as_mut().unwrap();
+        let args = &self.args;
+        let mut latency = Histogram::new();
+        let mut total = 0;
+        let start = Instant::now();
+        loop {
+            let mut left = args.ops;
+            total += ring.run(
+                || {
+                    if left == 0 {
+                        return None;
+                    }
+                    left -= 1;
+                    let write = offsets.rng().gen_range(0..100) >= args.read_percent;
+                    let offset = offsets.next().unwrap();
+                    Some(Op {
+                        offset,
+                        len: args.block_size,
+                        write,
+                    })
+                },
+                |_, _| {},
+                None,
+                &mut latency,
+                progress,
+            )?;
+            if progress.expired() || !progress.repeat() {
+                break;
+            }
+        }
+        Ok(Sample::new(total, start.elapsed()).with_latency(latency))
+    }
+}
+
+impl Benchmark for RandomBench {
+    fn setup(&mut self) -> Result<()> {
+        self.check()?;
+        self.args.engine.check()?;
+        let args = &self.args;
+        {
+            // The first worker creates the file, the others find it long enough.
+            let _created = CREATE.lock().unwrap_or_else(|e| e.into_inner());
+            let len = fs::metadata(&args.path).map(|m| m.len()).unwrap_or(0);
+            if len < args.file_size as u64 {
+                data::write_file(&args.path, args.file_size, Pattern::Random, args.seed)?;
+            }
+        }
+        let mut options = OpenOptions::new();
+        options.read(true).write(args.read_percent < 100);
+        let mut align = 1;
+        let file = if args.direct {
+            align = direct::check_aligned(&args.path, "--block-size", args.block_size)?;
+            if let Some(stride) = args.stride {
+                direct::check_aligned(&args.path, "--stride", stride)?;
+            }
+            direct::open_with(&mut options, 0, &args.path)?
+        } else {
+            options.open(&args.path)?
+        };
+        // Writes store random bytes, reads overwrite them.
+        let random = data::generate(args.block_size, Pattern::Random, args.seed);
+        if args.engine.is_uring() {
+            let mut ring = Ring::new(&args.engine, file, args.block_size, align)?;
+            for buf in ring.buffers_mut() {
+                buf.copy_from_slice(&random);
+            }
+            self.ring = Some(ring);
+        } else {
+            let mut buf = AlignedBuf::new(args.block_size, align);
+            buf.copy_from_slice(&random);
+            self.file = Some(file);
+            self.buf = Some(buf);
+        }
+        self.offsets = Some(Offsets::new(
+            args.distribution,
+            args.file_size as u64,
+            args.block_size as u64,
+            args.stride.unwrap_or(args.block_size) as u64,
+            args.theta,
+            args.seed,
+        ));
+        Ok(())
+    }
+    // Each repetition continues the offsets of the previous one, so that reads
+    // of a small file are not all served from the page cache after the first.
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        if self.ring.is_some() {
+            return self.run_uring(progress);
+        }
+        let file = self.file.as_ref().unwrap();
+        let offsets = self.offsets.as_mut().unwrap();
+        let buf = self.buf.as_mut().unwrap();
+        let mut latency = Histogram::new();
+        let mut total = 0;
+        let start = Instant::now();
+        'passes: loop {
+            for _i in 0..self.args.ops {
+                let read = offsets.rng().gen_range(0..100) < self.args.read_percent;
+                let offset = offsets.next().unwrap();
+                let op_start = Instant::now();
+                if read {
+                    file.read_exact_at(buf, offset)?;
+                } else {
+                    file.write_all_at(buf, offset)?;
+                }
+                latency.record_duration(op_start.elapsed());
+                progress.record(buf.len());
+                total += buf.len();
+                if progress.expired() {
+                    break 'passes;
+                }
+            }
+            if !progress.repeat() {
+                break;
+            }
+        }
+        Ok(Sample::new(total, start.elapsed()).with_latency(latency))
+    }
+    fn teardown(&mut self) -> Result<()> {
+        self.file = None;
+        self.buf = None;
+        self.offsets = None;
+        self.ring = None;
+        Ok(())
+    }
+    // Workers share the file and draw different offsets.
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.seed += worker as u64;
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_path(&self.args.path);
+    }
+}
+
+impl BenchmarkArgs for RandomArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(RandomBench {
+            args: self,
+            file: None,
+            offsets: None,
+            buf: None,
+            ring: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K: u64 = 1024;
+
+    fn sequential(file_size: u64, block_size: u64, stride: u64, n: usize) -> Vec<u64> {
+        Offsets::new(
+            Distribution::Sequential,
+            file_size,
+            block_size,
+            stride,
+            0f64,
+            0,
+        )
+        .take(n)
+        .map(|offset| offset / K)
+        .collect()
+    }
+
+    #[test]
+    fn sequential_offsets_wrap_around() {
+        assert_eq!(sequential(16 * K, 4 * K, 4 * K, 6), [0, 4, 8, 12, 0, 4]);
+        // Strided passes start one block later, until they are back at 0.
+        assert_eq!(sequential(16 * K, 4 * K, 8 * K, 6), [0, 8, 4, 12, 0, 8]);
+        assert_eq!(
+            sequential(16 * K, 4 * K, 6 * K, 8),
+            [0, 6, 12, 4, 10, 2, 8, 0]
+        );
+        // A file of a single block.
+        assert_eq!(sequential(4 * K, 4 * K, 4 * K, 3), [0, 0, 0]);
+    }
+
+    #[test]
+    fn random_offsets_are_blocks_of_the_file() {
+        for distribution in [Distribution::Uniform, Distribution::Zipfian] {
+            let offsets = Offsets::new(distribution, 1000 * K, 4 * K, 4 * K, 0.99, 1);
+            for offset in offsets.take(10000) {
+                assert_eq!(offset % (4 * K), 0);
+                assert!(offset + 4 * K <= 1000 * K);
+            }
+        }
+    }
+
+    #[test]
+    fn zeta_matches_the_sum() {
+        for theta in [0.01, 0.5, 0.99] {
+            for n in [1, 2, ZETA_TERMS, ZETA_TERMS + 1, 1_000_000] {
+                let sum = (1..=n).map(|i| (i as f64).powf(-theta)).sum::<f64>();
+                let rel = (zeta(n, theta) - sum).abs() / sum;
+                assert!(rel < 1e-12, "theta {} n {}: {}", theta, n, rel);
+            }
+        }
+    }
+
+    #[test]
+    fn zipfian_favors_low_ranks() {
+        let zipfian = Zipfian::new(1000, 0.99);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts = vec![0; 1000];
+        for _i in 0..100000 {
+            counts[zipfian.sample(&mut rng) as usize] += 1;
+        }
+        assert!(counts[0] > counts[1] && counts[1] > counts[10] && counts[10] > counts[999]);
+    }
+}
@@ -0,0 +1,281 @@
+//! Reads and writes through io_uring, with many operations in flight instead
+//! of one call at a time.
+use std::{fs::File, io, os::unix::io::AsRawFd, time::Instant};
+
+use clap::{ArgEnum, Args};
+use io_uring::{opcode, squeue, types, IoUring};
+use serde::Serialize;
+
+use crate::{
+    disk::{direct::AlignedBuf, durability::Syncer},
+    error::{Error, Result},
+    histogram::Histogram,
+    progress::Progress,
+};
+
+// How long the SQPOLL kernel thread spins without work before it sleeps.
+const SQPOLL_IDLE_MS: u32 = 1000;
+
+#[derive(ArgEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Engine {
+    /// One read or write call at a time.
+    Sync,
+    /// io_uring with up to --queue-depth operations in flight.
+    IoUring,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct EngineArgs {
+    /// How reads and writes are issued.
+    #[clap(long, arg_enum, default_value = "sync")]
+    engine: Engine,
+    /// Operations in flight with io-uring, each with its own buffer.
+    #[clap(long, default_value_t = 32)]
+    queue_depth: usize,
+    /// Submit once this many operations are queued, one io_uring_enter for
+    /// all of them.
+    #[clap(long, default_value_t = 1)]
+    batch: usize,
+    /// Register the buffers with the ring and use READ_FIXED and WRITE_FIXED.
+    #[clap(long)]
+    register_buffers: bool,
+    /// Register the file with the ring.
+    #[clap(long)]
+    register_files: bool,
+    /// Let a kernel thread poll the submission queue, so that submitting
+    /// needs no system call while it is awake.
+    #[clap(long)]
+    sqpoll: bool,
+}
+
+impl EngineArgs {
+    pub fn is_uring(&self) -> bool {
+        self.engine == Engine::IoUring
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if !self.is_uring() && (self.register_buffers || self.register_files || self.sqpoll) {
+            return Err(Error::Config(
+                "--register-buffers, --register-files and --sqpoll need --engine io-uring"
+                    .to_string(),
+            ));
+        }
+        if self.queue_depth == 0 || self.queue_depth > u16::MAX as usize {
+            return Err(Error::Config(format!(
+                "--queue-depth {} must be between 1 and {}",
+                self.queue_depth,
+                u16::MAX
+            )));
+        }
+        if self.batch == 0 || self.batch > self.queue_depth {
+            return Err(Error::Config(format!(
+                "--batch {} must be between 1 and --queue-depth {}",
+                self.batch, self.queue_depth
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// One read or write of `len` bytes at `offset`.
+pub struct Op {
+    pub offset: u64,
+    pub len: usize,
+    pub write: bool,
+}
+
+/// An io_uring over one file, with a buffer for every operation in flight.
+pub struct Ring {
+    // Dropped first, so that the kernel is done with the buffers and the file
+    // before they go.
+    ring: IoUring,
+    file: File,
+    bufs: Vec<AlignedBuf>,
+    free: Vec<usize>,
+    // Operation of every buffer in flight and when it was queued.
+    in_flight: Vec<Option<(Op, Instant)>>,
+    batch: usize,
+    fixed_buffers: bool,
+    fixed_file: bool,
+}
+
+impl Ring {
+    /// Sets up a ring of `queue_depth` entries for `file`, with buffers of
+    /// `buf_len` bytes aligned to `align`.
+    pub fn new(args: &EngineArgs, file: File, buf_len: usize, align: usize) -> Result<Self> {
+        let mut builder = IoUring::builder();
+        if args.sqpoll {
+            builder.setup_sqpoll(SQPOLL_IDLE_MS);
+        }
+        let ring = builder
+            .build(args.queue_depth as u32)
+            .map_err(|e| Error::Config(format!("cannot set up io_uring: {}", e)))?;
+        let bufs: Vec<_> = (0..args.queue_depth)
+            .map(|_| AlignedBuf::new(buf_len, align))
+            .collect();
+        if args.register_buffers {
+            let iovecs: Vec<_> = bufs
+                .iter()
+                .map(|buf| libc::iovec {
+                    iov_base: buf.as_ptr() as *mut libc::c_void,
+                    iov_len: buf.len(),
+                })
+                .collect();
+            // The buffers live as long as the ring.
+            unsafe { ring.submitter().register_buffers(&iovecs) }
+                .map_err(|e| Error::Config(format!("cannot register buffers: {}", e)))?;
+        }
+        if args.register_files {
+            ring.submitter()
+                .register_files(&[file.as_raw_fd()])
+                .map_err(|e| Error::Config(format!("cannot register the file: {}", e)))?;
+        }
+        Ok(Self {
+            ring,
+            file,
+            free: (0..bufs.len()).rev().collect(),
+            in_flight: (0..bufs.len()).map(|_| None).collect(),
+            bufs,
+            batch: args.batch,
+            fixed_buffers: args.register_buffers,
+            fixed_file: args.register_files,
+        })
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// The buffers, e.g. to fill them with the data of writes up front.
+    pub fn buffers_mut(&mut self) -> &mut [AlignedBuf] {
+        &mut self.bufs
+    }
+
+    /// Issues the operations `next` yields, keeping up to the queue depth in
+    /// flight, until it yields `None` or `progress` expires, and waits for all
+    /// of them. `fill` puts the data of a write into its buffer. Writes are
+    /// counted by `syncer`, and a periodic sync waits for the writes before it.
+    /// Returns the bytes read and written.
+    pub fn run(
+        &mut self,
+        mut next: impl FnMut() -> Option<Op>,
+        mut fill: impl FnMut(&Op, &mut [u8]),
+        mut syncer: Option<&mut Syncer>,
+        latency: &mut Histogram,
+        progress: &Progress,
+    ) -> Result<usize> {
+        let mut total = 0;
+        let mut queued = 0;
+        let mut pending = 0;
+        let mut done = false;
+        let mut sync_due = false;
+        let mut error = None;
+        loop {
+            while !done && !sync_due && !self.free.is_empty() {
+                let op = match next() {
+                    Some(op) => op,
+                    None => {
+                        done = true;
+                        break;
+                    }
+                };
+                let slot = self.free.pop().unwrap();
+                if op.write {
+                    fill(&op, &mut self.bufs[slot][..op.len]);
+                    if let Some(syncer) = syncer.as_deref_mut() {
+                        sync_due = syncer.wrote(op.len);
+                    }
+                }
+                let entry = self.entry(&op, slot);
+                self.in_flight[slot] = Some((op, Instant::now()));
+                // There are as many entries as buffers.
+                unsafe { self.ring.submission().push(&entry) }.unwrap();
+                queued += 1;
+                pending += 1;
+                if queued >= self.batch {
+                    self.ring.submit()?;
+                    queued = 0;
+                }
+            }
+            if pending == 0 {
+                if sync_due && error.is_none() {
+                    if let Some(syncer) = syncer.as_deref_mut() {
+                        syncer.sync(&self.file)?;
+                    }
+                    sync_due = false;
+                    continue;
+                }
+                break;
+            }
+            // Also submits what is left of a batch.
+            self.ring.submit_and_wait(1)?;
+            queued = 0;
+            for cqe in self.ring.completion() {
+                let slot = cqe.user_data() as usize;
+                let (op, start) = self.in_flight[slot].take().unwrap();
+                self.free.push(slot);
+                pending -= 1;
+                let res = cqe.result();
+                if res < 0 {
+                    error.get_or_insert(io::Error::from_raw_os_error(-res));
+                    done = true;
+                    continue;
+                }
+                // Reads at the end of the file come back short.
+                let len = res as usize;
+                if op.write && len != op.len {
+                    error.get_or_insert(io::Error::new(io::ErrorKind::WriteZero, "short write"));
+                    done = true;
+                    continue;
+                }
+                latency.record_duration(start.elapsed());
+                progress.record(len);
+                total += len;
+            }
+            if progress.expired() {
+                done = true;
+            }
+        }
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(total),
+        }
+    }
+
+    fn entry(&mut self, op: &Op, slot: usize) -> squeue::Entry {
+        let buf = self.bufs[slot].as_mut_ptr();
+        let len = op.len as u32;
+        let entry = match (self.fixed_file, self.fixed_buffers, op.write) {
+            (false, false, false) => opcode::Read::new(types::Fd(self.file.as_raw_fd()), buf, len)
+                .offset(op.offset)
+                .build(),
+            (false, false, true) => opcode::Write::new(types::Fd(self.file.as_raw_fd()), buf, len)
+                .offset(op.offset)
+                .build(),
+            (false, true, false) => {
+                opcode::ReadFixed::new(types::Fd(self.file.as_raw_fd()), buf, len, slot as u16)
+                    .offset(op.offset)
+                    .build()
+            }
+            (false, true, true) => {
+                opcode::WriteFixed::new(types::Fd(self.file.as_raw_fd()), buf, len, slot as u16)
+                    .offset(op.offset)
+                    .build()
+            }
+            (true, false, false) => opcode::Read::new(types::Fixed(0), buf, len)
+                .offset(op.offset)
+                .build(),
+            (true, false, true) => opcode::Write::new(types::Fixed(0), buf, len)
+                .offset(op.offset)
+                .build(),
+            (true, true, false) => opcode::ReadFixed::new(types::Fixed(0), buf, len, slot as u16)
+                .offset(op.offset)
+                .build(),
+            (true, true, true) => opcode::WriteFixed::new(types::Fixed(0), buf, len, slot as u16)
+                .offset(op.offset)
+                .build(),
+        };
+        entry.user_data(slot as u64)
+    }
+}
@@ -0,0 +1,418 @@
+//! Where a result was measured: CPU, kernel, file systems, NICs and RDMA
+//! devices. Everything is read from /proc, /sys, uname and the verbs API, so
+//! that results from different machines or clusters can be told apart later.
+use std::{
+    ffi::CStr,
+    fs,
+    os::raw::{c_char, c_void},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, rdma::verbs::IbvContext};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Environment {
+    pub cpu_model: String,
+    /// Kernel release and version as reported by uname.
+    pub kernel: String,
+    pub kernel_version: String,
+    /// File systems of the files the benchmark reads or writes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filesystems: Vec<Filesystem>,
+    /// Physical network interfaces.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nics: Vec<Nic>,
+    /// RDMA devices the benchmark uses.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rdma_devices: Vec<RdmaDevice>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Filesystem {
+    pub path: String,
+    pub mount_point: String,
+    pub fs_type: String,
+    pub device: String,
+    pub options: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Nic {
+    pub name: String,
+    pub driver: Option<String>,
+    pub firmware: Option<String>,
+    /// Mb/s, none if the link is down.
+    pub speed_mbps: Option<u64>,
+    pub mtu: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RdmaDevice {
+    pub name: String,
+    pub firmware: String,
+    pub node_guid: String,
+    pub vendor_id: u32,
+    pub vendor_part_id: u32,
+    pub hw_ver: u32,
+    pub max_qp: i32,
+    pub max_qp_wr: i32,
+    pub max_cq: i32,
+    pub max_cqe: i32,
+    pub max_mr_size: u64,
+    pub ports: Vec<RdmaPort>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RdmaPort {
+    pub port: u8,
+    pub state: String,
+    pub link_layer: String,
+    /// Bytes.
+    pub active_mtu: u32,
+    pub max_mtu: u32,
+    /// Width and speed as encoded by the verbs API.
+    pub active_width: u8,
+    pub active_speed: u8,
+    /// E.g. "100 Gb/sec (4X EDR)", from sysfs.
+    pub rate: Option<String>,
+    pub lid: u16,
+    /// Network interface of a RoCE port.
+    pub netdev: Option<String>,
+}
+
+impl Environment {
+    /// The CPU, kernel and NICs of this machine. Benchmarks add their files
+    /// and RDMA devices with `add_path` and `add_rdma_device`.
+    pub fn collect() -> Self {
+        let (kernel, kernel_version) = uname();
+        Self {
+            cpu_model: cpu_model(),
+            kernel,
+            kernel_version,
+            filesystems: Vec::new(),
+            nics: nics(),
+            rdma_devices: Vec::new(),
+        }
+    }
+
+    /// Adds the file system that holds `path`. The path does not need to exist
+    /// yet, e.g. the output of a write benchmark.
+    pub fn add_path(&mut self, path: &str) {
+        if self.filesystems.iter().any(|f| f.path == path) {
+            return;
+        }
+        if let Some(fs) = filesystem(path) {
+            self.filesystems.push(fs);
+        }
+    }
+
+    /// Adds the device and port attributes of an RDMA device. Failures are
+    /// only reported, they must not fail the benchmark.
+    pub fn add_rdma_device(&mut self, name: &str) {
+        if self.rdma_devices.iter().any(|d| d.name == name) {
+            return;
+        }
+        match rdma_device(name) {
+            Ok(dev) => self.rdma_devices.push(dev),
+            Err(e) => eprintln!("cannot query RDMA device {}: {}", name, e),
+        }
+    }
+
+    /// What differs between the machines of two results, e.g. "kernel: 5.4.0
+    /// vs 5.15.0". Empty if either environment was not recorded.
+    pub fn differences(&self, other: &Self) -> Vec<String> {
+        let mut diffs = Vec::new();
+        if self.kernel.is_empty() || other.kernel.is_empty() {
+            return diffs;
+        }
+        let mut check = |what: &str, a: &str, b: &str| {
+            if a != b {
+                diffs.push(format!("{}: {} vs {}", what, a, b));
+            }
+        };
+        check("cpu", &self.cpu_model, &other.cpu_model);
+        check("kernel", &self.kernel, &other.kernel);
+        for a in self.nics.iter() {
+            if let Some(b) = other.nics.iter().find(|b| b.name == a.name) {
+                let firmware = |n: &Nic| n.firmware.clone().unwrap_or_default();
+                check(&format!("{} firmware", a.name), &firmware(a), &firmware(b));
+                let speed = |n: &Nic| n.speed_mbps.map(|s| s.to_string()).unwrap_or_default();
+                check(&format!("{} speed(Mb/s)", a.name), &speed(a), &speed(b));
+            }
+        }
+        for a in self.rdma_devices.iter() {
+            if let Some(b) = other.rdma_devices.iter().find(|b| b.name == a.name) {
+                check(&format!("{} firmware", a.name), &a.firmware, &b.firmware);
+            }
+        }
+        diffs
+    }
+}
+
+fn read_trimmed<P: AsRef<Path>>(path: P) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn cpu_model() -> String {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    // x86 has "model name", other architectures use one of the others.
+    for key in ["model name", "Model", "cpu model", "Hardware", "cpu"].iter() {
+        let value = cpuinfo.lines().find_map(|line| {
+            let (k, v) = line.split_once(':')?;
+            if k.trim() == *key {
+                Some(v.trim().to_string())
+            } else {
+                None
+            }
+        });
+        if let Some(value) = value {
+            return value;
+        }
+    }
+    String::new()
+}
+
+fn uname() -> (String, String) {
+    let mut uts = unsafe { std::mem::zeroed::<libc::utsname>() };
+    if unsafe { libc::uname(&mut uts) } != 0 {
+        return (String::new(), String::new());
+    }
+    let field = |f: &[c_char]| unsafe { CStr::from_ptr(f.as_ptr()) }.to_string_lossy().into_owned();
+    (field(&uts.release), field(&uts.version))
+}
+
+// The mount with the longest mount point that contains the path, from
+// /proc/self/mountinfo.
+fn filesystem(path: &str) -> Option<Filesystem> {
+    let mut resolved = PathBuf::from(path);
+    let resolved = loop {
+        if let Ok(p) = resolved.canonicalize() {
+            break p;
+        }
+        match resolved.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => resolved = parent.to_path_buf(),
+            _ => break std::env::current_dir().ok()?,
+        }
+    };
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    let mut best: Option<Filesystem> = None;
+    for line in mountinfo.lines() {
+        // id parent major:minor root mount_point options [optional...] - type source super_options
+        let (mount, fs) = match line.split_once(" - ") {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let mount: Vec<&str> = mount.split(' ').collect();
+        let fs: Vec<&str> = fs.split(' ').collect();
+        if mount.len() < 6 || fs.len() < 2 {
+            continue;
+        }
+        let mount_point = unescape(mount[4]);
+        if !resolved.starts_with(&mount_point) {
+            continue;
+        }
+        if let Some(b) = &best {
+            if b.mount_point.len() > mount_point.len() {
+                continue;
+            }
+        }
+        best = Some(Filesystem {
+            path: path.to_string(),
+            mount_point,
+            fs_type: fs[0].to_string(),
+            device: unescape(fs[1]),
+            options: mount[5].to_string(),
+        });
+    }
+    best
+}
+
+// mountinfo escapes space, tab, newline and backslash as octal.
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        match rest.get(i + 1..i + 4).and_then(|o| u8::from_str_radix(o, 8).ok()) {
+            Some(c) => {
+                out.push(c as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Interfaces backed by a device, which leaves out loopback, bridges, veths and
+// other virtual interfaces.
+fn nics() -> Vec<Nic> {
+    let mut names: Vec<String> = match fs::read_dir("/sys/class/net") {
+        Ok(dir) => dir
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    names.sort();
+    names
+        .into_iter()
+        .filter(|name| Path::new("/sys/class/net").join(name).join("device").exists())
+        .map(|name| {
+            let sys = Path::new("/sys/class/net").join(&name);
+            let (driver, firmware) = match drvinfo(&name) {
+                Some((driver, firmware)) => (Some(driver), Some(firmware)),
+                None => (
+                    fs::read_link(sys.join("device/driver"))
+                        .ok()
+                        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned())),
+                    None,
+                ),
+            };
+            Nic {
+                driver,
+                firmware: firmware.filter(|f| !f.is_empty()),
+                // speed is -1 or unreadable while the link is down
+                speed_mbps: read_trimmed(sys.join("speed")).and_then(|s| s.parse().ok()),
+                mtu: read_trimmed(sys.join("mtu")).and_then(|s| s.parse().ok()),
+                name,
+            }
+        })
+        .collect()
+}
+
+const SIOCETHTOOL: u64 = 0x8946;
+const ETHTOOL_GDRVINFO: u32 = 0x3;
+
+// struct ethtool_drvinfo of linux/ethtool.h
+#[repr(C)]
+struct EthtoolDrvinfo {
+    cmd: u32,
+    driver: [c_char; 32],
+    version: [c_char; 32],
+    fw_version: [c_char; 32],
+    bus_info: [c_char; 32],
+    erom_version: [c_char; 32],
+    reserved2: [c_char; 12],
+    n_priv_flags: u32,
+    n_stats: u32,
+    testinfo_len: u32,
+    eedump_len: u32,
+    regdump_len: u32,
+}
+
+// struct ifreq with the ifr_data member of its union.
+#[repr(C)]
+struct IfreqData {
+    name: [c_char; libc::IFNAMSIZ],
+    data: *mut c_void,
+    pad: [u8; 16],
+}
+
+// Driver and firmware version of an interface, like `ethtool -i`.
+fn drvinfo(name: &str) -> Option<(String, String)> {
+    if name.len() >= libc::IFNAMSIZ {
+        return None;
+    }
+    let mut info = unsafe { std::mem::zeroed::<EthtoolDrvinfo>() };
+    info.cmd = ETHTOOL_GDRVINFO;
+    let mut ifr = IfreqData {
+        name: [0; libc::IFNAMSIZ],
+        data: &mut info as *mut _ as *mut c_void,
+        pad: [0; 16],
+    };
+    for (dst, src) in ifr.name.iter_mut().zip(name.bytes()) {
+        *dst = src as c_char;
+    }
+    let ret = unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+        if fd < 0 {
+            return None;
+        }
+        let ret = libc::ioctl(fd, SIOCETHTOOL as _, &mut ifr);
+        libc::close(fd);
+        ret
+    };
+    if ret != 0 {
+        return None;
+    }
+    let field = |f: &[c_char]| unsafe { CStr::from_ptr(f.as_ptr()) }.to_string_lossy().into_owned();
+    Some((field(&info.driver), field(&info.fw_version)))
+}
+
+fn rdma_device(name: &str) -> Result<RdmaDevice> {
+    let context = IbvContext::new(Some(name))?;
+    let attr = context.query_device()?;
+    let sys = Path::new("/sys/class/infiniband").join(name).join("ports");
+    let mut ports = Vec::new();
+    for port in 1..=attr.phys_port_cnt {
+        let p = context.query_port(port)?;
+        let port_sys = sys.join(port.to_string());
+        ports.push(RdmaPort {
+            port,
+            state: port_state(p.state).to_string(),
+            link_layer: match p.link_layer {
+                1 => "InfiniBand",
+                2 => "Ethernet",
+                _ => "unspecified",
+            }
+            .to_string(),
+            active_mtu: mtu_bytes(p.active_mtu),
+            max_mtu: mtu_bytes(p.max_mtu),
+            active_width: p.active_width,
+            active_speed: p.active_speed,
+            rate: read_trimmed(port_sys.join("rate")),
+            lid: p.lid,
+            netdev: read_trimmed(port_sys.join("gid_attrs/ndevs/0")).filter(|n| !n.is_empty()),
+        });
+    }
+    let guid = u64::from_be(attr.node_guid);
+    Ok(RdmaDevice {
+        name: name.to_string(),
+        firmware: unsafe { CStr::from_ptr(attr.fw_ver.as_ptr()) }
+            .to_string_lossy()
+            .into_owned(),
+        node_guid: format!(
+            "{:04x}:{:04x}:{:04x}:{:04x}",
+            guid >> 48,
+            (guid >> 32) & 0xffff,
+            (guid >> 16) & 0xffff,
+            guid & 0xffff
+        ),
+        vendor_id: attr.vendor_id,
+        vendor_part_id: attr.vendor_part_id,
+        hw_ver: attr.hw_ver,
+        max_qp: attr.max_qp,
+        max_qp_wr: attr.max_qp_wr,
+        max_cq: attr.max_cq,
+        max_cqe: attr.max_cqe,
+        max_mr_size: attr.max_mr_size,
+        ports,
+    })
+}
+
+// enum ibv_port_state
+fn port_state(state: u32) -> &'static str {
+    match state {
+        1 => "down",
+        2 => "init",
+        3 => "armed",
+        4 => "active",
+        5 => "active_defer",
+        _ => "nop",
+    }
+}
+
+// enum ibv_mtu: 1 is 256 bytes, 5 is 4096 bytes.
+fn mtu_bytes(mtu: u32) -> u32 {
+    match mtu {
+        1..=5 => 128 << mtu,
+        _ => 0,
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fmt, io};
+
+/// Error of every fallible function in the crate.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// A verbs or RDMA CM call failed.
+    Verbs { call: &'static str, errno: i32 },
+    /// The peer sent something unexpected, or a transfer did not complete.
+    Protocol(String),
+    /// Invalid arguments, devices or input files.
+    Config(String),
+    /// Transferred or written data does not match its source.
+    Verification(String),
+    /// A worker thread of a pool panicked in `phase`, setup or run.
+    WorkerPanicked { worker: usize, phase: &'static str },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// A failed call that reports its error through errno.
+    pub fn last_verbs(call: &'static str) -> Self {
+        let errno = io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        Error::Verbs { call, errno }
+    }
+
+    /// Verbs calls either return the errno, or -1 and set errno.
+    pub fn check_verbs(call: &'static str, ret: i32) -> Result<()> {
+        match ret {
+            0 => Ok(()),
+            -1 => Err(Self::last_verbs(call)),
+            errno => Err(Error::Verbs { call, errno }),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Verbs { call, errno } => write!(
+                f,
+                "{}() failed: {}",
+                call,
+                io::Error::from_raw_os_error(*errno)
+            ),
+            Error::Protocol(msg) => write!(f, "protocol error: {}", msg),
+            Error::Config(msg) => write!(f, "invalid configuration: {}", msg),
+            Error::Verification(msg) => write!(f, "verification failed: {}", msg),
+            Error::WorkerPanicked { worker, phase } => {
+                write!(f, "worker {} panicked in {}", worker, phase)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
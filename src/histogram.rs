@@ -0,0 +1,233 @@
+use std::{fmt, time::Duration};
+
+// Each power-of-two range of values is split into 2^SUB_BUCKET_BITS linear
+// sub-buckets, so every recorded value is kept with a relative error below
+// 1 / 2^SUB_BUCKET_BITS (under 1%), the same layout HdrHistogram uses.
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+pub const PERCENTILES: [f64; 4] = [50.0, 90.0, 99.0, 99.9];
+
+/// Latency histogram in nanoseconds with log-linear buckets.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.record_n(value, 1);
+    }
+
+    pub fn record_duration(&mut self, duration: Duration) {
+        self.record(duration.as_nanos() as u64);
+    }
+
+    pub fn record_n(&mut self, value: u64, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let index = bucket_index(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += n;
+        if self.total == 0 || value < self.min {
+            self.min = value;
+        }
+        if value > self.max {
+            self.max = value;
+        }
+        self.total += n;
+        self.sum += value as u128 * n as u128;
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.total == 0 {
+            return;
+        }
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c += o;
+        }
+        if self.total == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+        self.max = self.max.max(other.max);
+        self.total += other.total;
+        self.sum += other.sum;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+    pub fn mean(&self) -> f64 {
+        if self.total == 0 {
+            return 0f64;
+        }
+        self.sum as f64 / self.total as f64
+    }
+
+    /// Highest value equivalent to the bucket holding the p-th percentile.
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((p / 100.0 * self.total as f64).ceil() as u64).clamp(1, self.total);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let (low, width) = bucket_range(index);
+                return (low + (width - 1)).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Non-empty buckets as (low, high exclusive, count).
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c > 0)
+            .map(|(index, c)| {
+                let (low, width) = bucket_range(index);
+                (low, low.saturating_add(width), *c)
+            })
+    }
+
+    pub fn summary(&self) -> String {
+        let mut s = String::from("latency(us):");
+        for p in PERCENTILES.iter() {
+            s += &format!(" p{} {:.3},", p, self.percentile(*p) as f64 / 1e3);
+        }
+        s += &format!(" max {:.3}", self.max as f64 / 1e3);
+        s
+    }
+}
+
+// Prints the percentile summary followed by every non-empty bucket.
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} (n={})", self.summary(), self.total)?;
+        writeln!(f, "{:>14} {:>14} {:>12} {:>10}", "low(ns)", "high(ns)", "count", "cum%")?;
+        let mut seen = 0;
+        for (low, high, count) in self.buckets() {
+            seen += count;
+            writeln!(
+                f,
+                "{:>14} {:>14} {:>12} {:>9.3}%",
+                low,
+                high,
+                count,
+                seen as f64 * 100.0 / self.total as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS {
+        return value as usize;
+    }
+    let exp = 63 - value.leading_zeros();
+    let shift = exp - SUB_BUCKET_BITS;
+    let mantissa = value >> shift;
+    ((shift as u64 + 1) * SUB_BUCKETS + (mantissa - SUB_BUCKETS)) as usize
+}
+
+// Lowest value and width of a bucket.
+fn bucket_range(index: usize) -> (u64, u64) {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return (index, 1);
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let mantissa = SUB_BUCKETS + index % SUB_BUCKETS;
+    (mantissa << shift, 1 << shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_values_are_exact() {
+        let mut h = Histogram::new();
+        for v in 1..=100 {
+            h.record(v);
+        }
+        let percentiles: Vec<u64> = PERCENTILES.iter().map(|p| h.percentile(*p)).collect();
+        assert_eq!(percentiles, [50, 90, 99, 100]);
+        assert_eq!((h.min(), h.max(), h.count()), (1, 100, 100));
+        assert_eq!(h.mean(), 50.5);
+    }
+
+    #[test]
+    fn buckets_hold_their_values_within_one_percent() {
+        let mut v = 1u64;
+        while v < u64::MAX / 3 {
+            for value in [v, v * 3 / 2, v * 2 - 1, v * 3] {
+                let (low, width) = bucket_range(bucket_index(value));
+                assert!(low <= value && value - low < width, "{}", value);
+                assert!(width == 1 || (width as f64) / (low as f64) <= 1f64 / SUB_BUCKETS as f64);
+            }
+            v *= 2;
+        }
+        let (low, width) = bucket_range(bucket_index(u64::MAX));
+        assert_eq!(low + (width - 1), u64::MAX);
+    }
+
+    #[test]
+    fn quantiles_of_large_values() {
+        let mut h = Histogram::new();
+        // 90 fast and 10 slow operations.
+        h.record_n(1_000, 90);
+        h.record_n(1_000_000, 10);
+        // The highest value of the bucket [1000, 1004).
+        assert_eq!(h.percentile(50.0), 1_003);
+        assert_eq!(h.percentile(90.0), 1_003);
+        let p99 = h.percentile(99.0);
+        assert!(p99 >= 1_000_000 && p99 - 1_000_000 < 1_000_000 / 128);
+        assert_eq!(h.percentile(100.0), 1_000_000);
+        h.record(u64::MAX);
+        assert_eq!(h.percentile(100.0), u64::MAX);
+    }
+
+    #[test]
+    fn merge_adds_counts() {
+        let (mut a, mut b, mut all) = (Histogram::new(), Histogram::new(), Histogram::new());
+        for v in 0..1000 {
+            if v % 2 == 0 { &mut a } else { &mut b }.record(v * 37);
+            all.record(v * 37);
+        }
+        let empty = Histogram::new();
+        a.merge(&b);
+        a.merge(&empty);
+        assert_eq!(a.buckets().collect::<Vec<_>>(), all.buckets().collect::<Vec<_>>());
+        assert_eq!((a.min(), a.max(), a.count()), (all.min(), all.max(), all.count()));
+        assert_eq!(a.percentile(99.0), all.percentile(99.0));
+        assert_eq!(empty.percentile(50.0), 0);
+    }
+}
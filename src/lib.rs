@@ -1 +1,63 @@
-pub mod connection;
\ No newline at end of file
+//! Disk, serialization and RDMA throughput benchmarks.
+//!
+//! The `benchmark` binary is a thin command line front end over this crate.
+//! Other programs can run the same workloads, e.g. as self-tests:
+//!
+//! ```no_run
+//! let registry = benchmark::registry();
+//! let entry = registry.get("disk-read").unwrap();
+//! let measurement = entry.run(&["--buf-len", "1048576", "--repetitions", "3"])?;
+//! println!("{}", measurement);
+//! # Ok::<(), benchmark::error::Error>(())
+//! ```
+//!
+//! The building blocks are
+//!
+//! - [`measure`]: samples, measurements and their statistics,
+//!   [`histogram`] for per-operation latency, [`progress`] for bandwidth
+//!   over time and [`usage`] for the CPU time and faults it cost.
+//! - [`bench`](mod@bench): the [`Benchmark`](bench::Benchmark) trait and the
+//!   registry of named benchmarks, [`pool`] to run one on several threads and
+//!   [`coordinator`] to run a server and client pair on two hosts.
+//! - [`disk`], [`serial`] and [`net`]: file, serialization and TCP workloads.
+//! - [`rdma`]: RDMA read/write workloads and the [`verbs`](rdma::verbs)
+//!   wrappers they are built on, [`connection`] for the TCP handshake used by
+//!   the examples.
+//! - [`data`] and [`checksum`]: generated inputs and verification.
+//! - [`result`], [`compare`], [`suite`] and [`sweep`]: result files and the
+//!   command line features built on them, [`environment`] for the machine
+//!   a result was measured on.
+
+pub mod bench;
+pub mod checksum;
+pub mod compare;
+pub mod connection;
+pub mod coordinator;
+pub mod data;
+pub mod disk;
+pub mod environment;
+pub mod error;
+pub mod histogram;
+pub mod measure;
+pub mod net;
+pub mod numa;
+pub mod pool;
+pub mod progress;
+pub mod rdma;
+pub mod result;
+pub mod serial;
+pub mod suite;
+pub mod sweep;
+pub mod usage;
+
+use bench::Registry;
+
+/// Every benchmark of the crate, keyed by its subcommand name.
+pub fn registry() -> Registry {
+    let mut registry = Registry::default();
+    disk::register(&mut registry);
+    serial::register(&mut registry);
+    net::register(&mut registry);
+    rdma::register(&mut registry);
+    registry
+}
@@ -1,49 +1,246 @@
-#![allow(dead_code, unused_imports)]
-use crate::disk::file_rw::read_throughput;
-use clap::Parser;
-use clap::Subcommand;
-use disk::file_rw::{bufread_throughput, bufwrite_throughput};
-use rdma::{
-    read::{test_rclient, test_rserver},
-    write::{test_wclient, test_wserver},
+use benchmark::{
+    bench::{self, Registry},
+    compare::{self, CompareArgs},
+    coordinator::{self, AgentArgs, CoordinateArgs},
+    data::GenDataArgs,
+    environment::Environment,
+    error::{self, Error},
+    measure::MeasureArgs,
+    numa::Placement,
+    pool, registry,
+    result::{self, BenchResult, OutputArgs, OutputFormat},
+    suite::SuiteArgs,
+    sweep,
 };
-use serial::serialize::test_serialize;
-mod disk;
-mod net;
-mod rdma;
-mod serial;
-pub mod connection;
+use clap::{App, AppSettings, ArgMatches, Args as _, FromArgMatches, IntoApp, Parser};
+
 #[derive(Parser, Debug)]
 #[clap(about, version, author)]
 struct Args {
-    #[clap(long)]
-    bench: String,
-    #[clap(long, default_value = "")]
-    rdma: String,
-    #[clap(long, default_value = "")]
-    disk: String,
+    #[clap(flatten)]
+    output: OutputArgs,
 }
-    
+
+fn app(registry: &Registry) -> App<'static> {
+    let mut app = Args::into_app()
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(App::new("list").about("List the registered benchmarks"))
+        .subcommand(CompareArgs::augment_args(
+            App::new("compare").about("Compare two result files and detect regressions"),
+        ))
+        .subcommand(SuiteArgs::augment_args(
+            App::new("run-suite").about("Run every step of a scenario file"),
+        ))
+        .subcommand(GenDataArgs::augment_args(
+            App::new("gen-data").about("Generate a synthetic input file"),
+        ))
+        .subcommand(AgentArgs::augment_args(
+            App::new("agent").about("Run benchmarks on behalf of a coordinator"),
+        ))
+        .subcommand(CoordinateArgs::augment_args(
+            App::new("coordinate").about("Run both sides of a benchmark pair on two agents"),
+        ));
+    for entry in registry.iter() {
+        app = app.subcommand(entry.command());
+    }
+    app
+}
+
+// Parses every variant of a swept command line, so that a bad value is
+// reported before any of them runs.
+fn parse_variants(registry: &Registry, variants: &[Vec<String>]) -> clap::Result<Vec<ArgMatches>> {
+    variants
+        .iter()
+        .map(|variant| app(registry).try_get_matches_from(variant))
+        .collect()
+}
+
+// Runs the benchmark selected by each of `variants`, the parsed sweep of one
+// command line. `report` prints the results as text, prefixed with the
+// parameters if there is more than one variant.
+fn run_benchmark(
+    registry: &Registry,
+    variants: &[ArgMatches],
+    report: bool,
+) -> error::Result<Vec<BenchResult>> {
+    let header = variants.len() > 1;
+    variants
+        .iter()
+        .map(|matches| run_variant(registry, matches, report, header))
+        .collect()
+}
+
+fn run_variant(
+    registry: &Registry,
+    matches: &ArgMatches,
+    report: bool,
+    header: bool,
+) -> error::Result<BenchResult> {
+    let (name, sub_matches) = matches.subcommand().unwrap();
+    let entry = registry.get(name).unwrap();
+    let config = |e: clap::Error| Error::Config(e.to_string());
+    let (mut bench, parameters) = entry.build(sub_matches).map_err(config)?;
+    let measure_args = MeasureArgs::from_arg_matches(sub_matches).map_err(config)?;
+    let placement = Placement::new(&measure_args.placement, measure_args.threads)?;
+    let mut environment = Environment::collect();
+    bench.describe(&mut environment);
+    if measure_args.threads > 1 {
+        let pool = pool::execute(entry, sub_matches, &measure_args, placement.as_ref())?;
+        if report {
+            if header {
+                println!("{} {}", name, result::parameter_string(&parameters));
+            }
+            println!("{}", pool);
+        }
+        return Ok(BenchResult::new(name, parameters, measure_args, &pool.total)
+            .with_workers(&pool)
+            .with_placement(placement)
+            .with_environment(environment));
+    }
+    if let Some(placement) = &placement {
+        placement.apply(0)?;
+    }
+    let measurement = bench::execute(bench.as_mut(), &measure_args)?;
+    if report {
+        if header {
+            println!("{} {}", name, result::parameter_string(&parameters));
+        }
+        bench.report(&measurement);
+    }
+    Ok(BenchResult::new(name, parameters, measure_args, &measurement)
+        .with_placement(placement)
+        .with_environment(environment))
+}
+
+// Checks the command lines of all steps before running the first one. A step
+// that fails is reported and skipped, the results of the others are still
+// written. Returns the number of failed steps.
+fn run_suite(registry: &Registry, suite_args: &SuiteArgs, output: &OutputArgs) -> error::Result<usize> {
+    let scenario = suite_args.load()?;
+    scenario.check_data_files()?;
+    let mut steps = Vec::with_capacity(scenario.steps.len());
+    for (i, step) in scenario.steps.iter().enumerate() {
+        let argv = scenario.command_line(step);
+        let variants = parse_variants(registry, &sweep::expand_args(&argv))
+            .map_err(|e| Error::Config(format!("step {} ({}): {}", i + 1, step.benchmark, e)))?;
+        steps.push((argv, variants));
+    }
+    let mut results = Vec::new();
+    let mut failed = 0;
+    for (i, (step, (argv, variants))) in scenario.steps.iter().zip(steps).enumerate() {
+        eprintln!("[{}/{}] {}", i + 1, scenario.steps.len(), argv[1..].join(" "));
+        let step_results = match run_benchmark(registry, &variants, false) {
+            Ok(step_results) => step_results,
+            Err(e) => {
+                eprintln!("[{}/{}] failed: {}", i + 1, scenario.steps.len(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        let step_results: Vec<_> = step_results
+            .into_iter()
+            .map(|r| r.with_suite(scenario.name.clone()))
+            .collect();
+        if let Some(path) = &step.output {
+            let step_output = OutputArgs {
+                output: OutputFormat::Json,
+                output_file: Some(path.clone()),
+            };
+            result::write_results(&step_results, &step_output)?;
+        }
+        results.extend(step_results);
+    }
+    let output = OutputArgs {
+        output: scenario.format.unwrap_or(output.output),
+        output_file: scenario.output.clone().or_else(|| output.output_file.clone()),
+    };
+    result::write_results(&results, &output)?;
+    Ok(failed)
+}
+
 fn main() {
-    let args = Args::parse();
-    if args.bench == "disk" {
-        if args.disk == "read" {
-            read_throughput("data/bigfile.log", 4096000);
-            bufread_throughput("data/bigfile.log", 4096000);
-        } else if args.disk == "write" {
-            bufwrite_throughput("data/bigfile.log", "log/bigfile.log", 1024000);
-        }
-    } else if args.bench == "serial" {
-        test_serialize("data/bigfile.log", 1024);
-    } else if args.bench == "rdma" {
-        if args.rdma == "read_server" {
-            test_rserver("127.0.0.1:9500", "data/bigfile.log");
-        } else if args.rdma == "read_client" {
-            test_rclient("127.0.0.1:9500");
-        } else if args.rdma == "write_server" {
-            test_wserver("10.0.12.24:9500", "data/bigfile.log");
-        } else if args.rdma == "write_client" {
-            test_wclient("10.0.12.24:9500");
+    let registry = registry();
+    let argv: Vec<String> = std::env::args().collect();
+    // Every swept option multiplies the runs, see `sweep`.
+    let variants = sweep::expand_args(&argv);
+    let matches = app(&registry).get_matches_from(&variants[0]);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    match matches.subcommand() {
+        Some(("list", _)) => {
+            for entry in registry.iter() {
+                println!("{:<20} {}", entry.name, entry.about);
+            }
+            for pair in registry.pairs() {
+                println!("{:<20} pair of {} and {}", pair.name, pair.server, pair.client);
+            }
+        }
+        Some(("compare", sub_matches)) => {
+            let compare_args = CompareArgs::from_arg_matches(sub_matches).unwrap_or_else(|e| e.exit());
+            match compare::run(&compare_args) {
+                Ok(false) => {}
+                Ok(true) => std::process::exit(2),
+                Err(e) => {
+                    eprintln!("compare failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("run-suite", sub_matches)) => {
+            let suite_args = SuiteArgs::from_arg_matches(sub_matches).unwrap_or_else(|e| e.exit());
+            match run_suite(&registry, &suite_args, &args.output) {
+                Ok(0) => {}
+                Ok(failed) => {
+                    eprintln!("run-suite: {} steps failed", failed);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("run-suite failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("agent", sub_matches)) => {
+            let agent_args = AgentArgs::from_arg_matches(sub_matches).unwrap_or_else(|e| e.exit());
+            if let Err(e) = coordinator::serve(&registry, &agent_args) {
+                eprintln!("agent failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(("coordinate", sub_matches)) => {
+            let coordinate_args =
+                CoordinateArgs::from_arg_matches(sub_matches).unwrap_or_else(|e| e.exit());
+            let res = coordinator::coordinate(&registry, &coordinate_args)
+                .and_then(|results| result::write_results(&results, &args.output));
+            if let Err(e) = res {
+                eprintln!("coordinate failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(("gen-data", sub_matches)) => {
+            let gen_args = GenDataArgs::from_arg_matches(sub_matches).unwrap_or_else(|e| e.exit());
+            if let Err(e) = gen_args.run() {
+                eprintln!("gen-data failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some((name, _)) => {
+            let report = args.output.output == OutputFormat::Text && args.output.output_file.is_none();
+            let variants = parse_variants(&registry, &variants).unwrap_or_else(|e| e.exit());
+            let results = match run_benchmark(&registry, &variants, report) {
+                Ok(results) => results,
+                Err(e) => {
+                    eprintln!("{} failed: {}", name, e);
+                    std::process::exit(1);
+                }
+            };
+            if report {
+                return;
+            }
+            if let Err(e) = result::write_results(&results, &args.output) {
+                eprintln!("failed to write results: {}", e);
+                std::process::exit(1);
+            }
         }
+        None => unreachable!(),
     }
 }
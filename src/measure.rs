@@ -0,0 +1,627 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    histogram::Histogram,
+    numa::PlacementArgs,
+    progress::{Interval, Progress},
+    usage::Usage,
+};
+
+// Two-sided 95% Student's t critical values for 1..=30 degrees of freedom.
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// How often a benchmark runs, common to every benchmark subcommand.
+#[derive(Args, Serialize, Deserialize, Debug, Clone)]
+pub struct MeasureArgs {
+    /// Unmeasured iterations before sampling starts.
+    #[clap(long, default_value_t = 1)]
+    pub warmup: usize,
+    /// Measured iterations.
+    #[clap(long, default_value_t = 10)]
+    pub repetitions: usize,
+    /// Sample until this many seconds are measured instead of a fixed count.
+    #[clap(long)]
+    pub target_time: Option<f64>,
+    /// Run the workload over and over until this time is up, e.g. 30s or 5m,
+    /// instead of --repetitions.
+    #[clap(long, parse(try_from_str = parse_duration))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// Print bandwidth and ops/s every interval, e.g. 1s. Defaults to 1s with --duration.
+    #[clap(long, parse(try_from_str = parse_duration))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report_interval: Option<f64>,
+    /// Exclude samples outside the 1.5 IQR fences from the statistics.
+    #[clap(long)]
+    pub drop_outliers: bool,
+    /// Worker threads, each running its own instance of the benchmark.
+    #[clap(long, default_value_t = 1)]
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    pub placement: PlacementArgs,
+}
+
+fn default_threads() -> usize {
+    1
+}
+
+impl MeasureArgs {
+    pub fn report_interval(&self) -> Option<Duration> {
+        self.report_interval
+            .or_else(|| self.duration.map(|_| 1f64))
+            .map(Duration::from_secs_f64)
+    }
+}
+
+/// Seconds from `30s`, `500ms`, `5m`, `1h` or a plain number of seconds.
+pub fn parse_duration(s: &str) -> std::result::Result<f64, String> {
+    let (value, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => (&s[..i], &s[i..]),
+        None => (s, "s"),
+    };
+    let scale = match unit {
+        "ms" => 1e-3,
+        "s" => 1f64,
+        "m" => 60f64,
+        "h" => 3600f64,
+        _ => return Err(format!("unknown time unit in {}", s)),
+    };
+    match value.parse::<f64>() {
+        Ok(v) if v > 0f64 && v.is_finite() => Ok(v * scale),
+        _ => Err(format!("invalid duration {}", s)),
+    }
+}
+
+/// One measured iteration of a workload.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub bytes: usize,
+    pub duration: Duration,
+    /// Per-operation latency, empty if the workload does not time operations.
+    pub latency: Histogram,
+    /// Latency of the calls that made written data durable, if any.
+    pub sync_latency: Histogram,
+    /// Resources used by the iteration, filled in by `measure`.
+    pub usage: Usage,
+    /// Fraction of the input in the page cache when the iteration started.
+    pub cache_residency: Option<f64>,
+}
+
+impl Sample {
+    pub fn new(bytes: usize, duration: Duration) -> Self {
+        Self {
+            bytes,
+            duration,
+            latency: Histogram::new(),
+            sync_latency: Histogram::new(),
+            usage: Usage::default(),
+            cache_residency: None,
+        }
+    }
+    pub fn with_latency(mut self, latency: Histogram) -> Self {
+        self.latency = latency;
+        self
+    }
+    pub fn with_sync_latency(mut self, sync_latency: Histogram) -> Self {
+        self.sync_latency = sync_latency;
+        self
+    }
+    pub fn with_usage(mut self, usage: Usage) -> Self {
+        self.usage = usage;
+        self
+    }
+    pub fn with_cache_residency(mut self, residency: Option<f64>) -> Self {
+        self.cache_residency = residency;
+        self
+    }
+    /// MB/s
+    pub fn throughput(&self) -> f64 {
+        let size = self.bytes as f64 / (1024f64 * 1024f64);
+        size / self.duration.as_secs_f64()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+/// Statistics of the per-sample throughput. Values that are not finite, e.g.
+/// of a sample that took no time, are left out.
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub ci95: (f64, f64),
+}
+
+impl Summary {
+    pub fn new(values: &[f64]) -> Self {
+        let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        let n = sorted.len();
+        if n == 0 {
+            return Self::default();
+        }
+        sorted.sort_by(f64::total_cmp);
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let stddev = if n > 1 {
+            let var = sorted.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1) as f64;
+            var.sqrt()
+        } else {
+            0f64
+        };
+        let half_width = if n > 1 {
+            t_95(n - 1) * stddev / (n as f64).sqrt()
+        } else {
+            0f64
+        };
+        Self {
+            n,
+            mean,
+            median: percentile(&sorted, 0.5),
+            stddev,
+            min: sorted[0],
+            max: sorted[n - 1],
+            ci95: (mean - half_width, mean + half_width),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean {:.3}, median {:.3}, stddev {:.3}, min {:.3}, max {:.3}, 95% CI [{:.3}, {:.3}] (n={})",
+            self.mean,
+            self.median,
+            self.stddev,
+            self.min,
+            self.max,
+            self.ci95.0,
+            self.ci95.1,
+            self.n
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+/// All samples of a run and their statistics.
+pub struct Measurement {
+    pub samples: Vec<Sample>,
+    /// Indices into `samples` outside the 1.5 IQR fences.
+    pub outliers: Vec<usize>,
+    /// Throughput in MB/s.
+    pub throughput: Summary,
+    /// Per-operation latency merged over all samples.
+    pub latency: Histogram,
+    /// Sync latency merged over all samples.
+    pub sync_latency: Histogram,
+    /// Bandwidth over time, recorded with --report-interval or --duration.
+    pub intervals: Vec<Interval>,
+    /// Resources used by all samples.
+    pub usage: Usage,
+}
+
+impl Measurement {
+    pub fn new(samples: Vec<Sample>, drop_outliers: bool) -> Self {
+        let values: Vec<f64> = samples.iter().map(|s| s.throughput()).collect();
+        let outliers = outliers(&values);
+        let kept: Vec<f64> = if drop_outliers {
+            values
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !outliers.contains(i))
+                .map(|(_, v)| *v)
+                .collect()
+        } else {
+            values
+        };
+        let mut latency = Histogram::new();
+        let mut sync_latency = Histogram::new();
+        let mut usage = Usage::default();
+        for sample in samples.iter() {
+            latency.merge(&sample.latency);
+            sync_latency.merge(&sample.sync_latency);
+            usage.add(&sample.usage);
+        }
+        Self {
+            samples,
+            outliers,
+            throughput: Summary::new(&kept),
+            latency,
+            sync_latency,
+            intervals: Vec::new(),
+            usage,
+        }
+    }
+    pub fn with_intervals(mut self, intervals: Vec<Interval>) -> Self {
+        self.intervals = intervals;
+        self
+    }
+    pub fn total_bytes(&self) -> usize {
+        self.samples.iter().map(|s| s.bytes).sum()
+    }
+    pub fn total_duration(&self) -> Duration {
+        self.samples.iter().map(|s| s.duration).sum()
+    }
+    /// Timed operations per second, none if the workload does not time them.
+    pub fn ops_per_sec(&self) -> Option<f64> {
+        let secs = self.total_duration().as_secs_f64();
+        if self.latency.is_empty() || secs <= 0f64 {
+            None
+        } else {
+            Some(self.latency.count() as f64 / secs)
+        }
+    }
+    /// MB moved per second of CPU time, none if no CPU time was measured.
+    pub fn mb_per_cpu_s(&self) -> Option<f64> {
+        let cpu = self.usage.cpu_s();
+        if cpu > 0f64 {
+            Some(self.total_bytes() as f64 / (1024f64 * 1024f64) / cpu)
+        } else {
+            None
+        }
+    }
+    /// CPU time per second of measured time, 1 for a busy core.
+    pub fn cpu_utilization(&self) -> Option<f64> {
+        let secs = self.total_duration().as_secs_f64();
+        if secs > 0f64 {
+            Some(self.usage.cpu_s() / secs)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "throughput(MB/s): {}", self.throughput)?;
+        if !self.outliers.is_empty() {
+            write!(f, ", {} outlier(s)", self.outliers.len())?;
+        }
+        if let Some(ops) = self.ops_per_sec() {
+            write!(f, "\nops/s: {:.1}", ops)?;
+        }
+        if let (Some(mb), Some(cores)) = (self.mb_per_cpu_s(), self.cpu_utilization()) {
+            write!(f, "\nMB per cpu-second: {:.3}, cores busy: {:.2}", mb, cores)?;
+        }
+        write!(f, "\n{}", self.usage)?;
+        if self.samples.iter().any(|s| s.cache_residency.is_some()) {
+            write!(f, "\npage cache residency(%):")?;
+            for s in self.samples.iter() {
+                match s.cache_residency {
+                    Some(r) => write!(f, " {:.1}", r * 100f64)?,
+                    None => write!(f, " -")?,
+                }
+            }
+        }
+        if !self.sync_latency.is_empty() {
+            write!(f, "\nsync {} (n={})", self.sync_latency.summary(), self.sync_latency.count())?;
+        }
+        if !self.latency.is_empty() {
+            write!(f, "\n{}", self.latency)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `f` for the configured warmup and measured iterations. `limit` fixes the
+/// number of iterations for workloads that can only run a fixed number of times.
+/// With --duration a single iteration repeats the workload until the deadline.
+/// `prepare` runs before every iteration and is not part of its usage.
+pub fn measure<P, F>(
+    args: &MeasureArgs,
+    limit: Option<usize>,
+    progress: &Progress,
+    mut prepare: P,
+    mut f: F,
+) -> Result<Measurement>
+where
+    P: FnMut() -> Result<()>,
+    F: FnMut(&Progress) -> Result<Sample>,
+{
+    let (warmup, repetitions, target_time, duration) = match limit {
+        Some(limit) => (0, limit, None, None),
+        None => (args.warmup, args.repetitions, args.target_time, args.duration),
+    };
+    for _i in 0..warmup {
+        prepare()?;
+        f(&Progress::new())?;
+    }
+    let mut f = |progress: &Progress| -> Result<Sample> {
+        let start = Usage::now();
+        let sample = f(progress)?;
+        Ok(sample.with_usage(Usage::now().since(&start)))
+    };
+    let mut samples = Vec::new();
+    match (duration, target_time) {
+        (Some(secs), _) => {
+            prepare()?;
+            progress.start();
+            let deadline = Instant::now() + Duration::from_secs_f64(secs);
+            samples.push(f(&progress.until(deadline))?);
+        }
+        (None, Some(secs)) => {
+            progress.start();
+            let target = Duration::from_secs_f64(secs);
+            let start = Instant::now();
+            while samples.is_empty() || start.elapsed() < target {
+                prepare()?;
+                samples.push(f(progress)?);
+            }
+        }
+        (None, None) => {
+            progress.start();
+            for _i in 0..repetitions.max(1) {
+                prepare()?;
+                samples.push(f(progress)?);
+            }
+        }
+    }
+    Ok(Measurement::new(samples, args.drop_outliers))
+}
+
+fn t_95(df: usize) -> f64 {
+    match df {
+        0 => f64::NAN,
+        1..=30 => T_95[df - 1],
+        31..=40 => 2.021,
+        41..=60 => 2.000,
+        61..=120 => 1.980,
+        _ => 1.960,
+    }
+}
+
+// Linear interpolation between the closest ranks of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+// Tukey's fences: values outside [q1 - 1.5 IQR, q3 + 1.5 IQR], and values that
+// are not finite.
+fn outliers(values: &[f64]) -> Vec<usize> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    sorted.sort_by(f64::total_cmp);
+    let (lo, hi) = if sorted.len() < 4 {
+        (f64::NEG_INFINITY, f64::INFINITY)
+    } else {
+        let q1 = percentile(&sorted, 0.25);
+        let q3 = percentile(&sorted, 0.75);
+        let iqr = q3 - q1;
+        (q1 - 1.5 * iqr, q3 + 1.5 * iqr)
+    };
+    values
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| !v.is_finite() || **v < lo || **v > hi)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Two-sided p-value of Welch's t-test for a difference between the means of
+/// two summaries. None if either side has fewer than two samples.
+pub fn welch_t_test(a: &Summary, b: &Summary) -> Option<f64> {
+    if a.n < 2 || b.n < 2 {
+        return None;
+    }
+    let va = a.stddev * a.stddev / a.n as f64;
+    let vb = b.stddev * b.stddev / b.n as f64;
+    if va + vb == 0f64 {
+        return Some(if a.mean == b.mean { 1f64 } else { 0f64 });
+    }
+    let t = (a.mean - b.mean) / (va + vb).sqrt();
+    let df = (va + vb) * (va + vb) / (va * va / (a.n - 1) as f64 + vb * vb / (b.n - 1) as f64);
+    Some(incomplete_beta(df / 2f64, 0.5, df / (df + t * t)))
+}
+
+fn ln_gamma(x: f64) -> f64 {
+    // Lanczos approximation, g = 7.
+    const COEF: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1f64 - x);
+    }
+    let x = x - 1f64;
+    let mut sum = COEF[0];
+    for (i, c) in COEF.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2f64 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// Regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0f64 {
+        return 0f64;
+    }
+    if x >= 1f64 {
+        return 1f64;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1f64 - x).ln()).exp();
+    if x < (a + 1f64) / (a + b + 2f64) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1f64 - front * beta_fraction(b, a, 1f64 - x) / b
+    }
+}
+
+// Continued fraction for the incomplete beta function (modified Lentz).
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1f64;
+    let mut d = 1f64 - (a + b) * x / (a + 1f64);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1f64 / d;
+    let mut h = d;
+    for m in 1..200 {
+        let m = m as f64;
+        let aa = m * (b - m) * x / ((a + 2f64 * m - 1f64) * (a + 2f64 * m));
+        d = 1f64 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1f64 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1f64 / d;
+        h *= d * c;
+        let aa = -(a + m) * (a + b + m) * x / ((a + 2f64 * m) * (a + 2f64 * m + 1f64));
+        d = 1f64 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1f64 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1f64 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1f64).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn summary_and_confidence_interval() {
+        let s = Summary::new(&[3.0, 1.0, 2.0, 5.0, 4.0]);
+        assert_eq!(s.n, 5);
+        assert!(close(s.mean, 3.0));
+        assert!(close(s.median, 3.0));
+        assert!(close(s.stddev, 2.5f64.sqrt()));
+        assert_eq!((s.min, s.max), (1.0, 5.0));
+        // t(0.975, 4) = 2.776
+        let half_width = 2.776 * 2.5f64.sqrt() / 5f64.sqrt();
+        assert!(close(s.ci95.0, 3.0 - half_width));
+        assert!(close(s.ci95.1, 3.0 + half_width));
+    }
+
+    #[test]
+    fn summary_of_one_value_has_no_spread() {
+        let s = Summary::new(&[7.0]);
+        assert_eq!((s.n, s.stddev, s.ci95), (1, 0.0, (7.0, 7.0)));
+        assert_eq!(Summary::new(&[]).n, 0);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30s"), Ok(30.0));
+        assert_eq!(parse_duration("30"), Ok(30.0));
+        assert_eq!(parse_duration("1.5"), Ok(1.5));
+        assert_eq!(parse_duration("5m"), Ok(300.0));
+        assert_eq!(parse_duration("1h"), Ok(3600.0));
+        assert!(close(parse_duration("500ms").unwrap(), 0.5));
+        for s in ["", "s", "0s", "-1s", "10d", "1 s", "infs", "NaN"] {
+            assert!(parse_duration(s).is_err(), "{}", s);
+        }
+    }
+
+    fn summary(mean: f64, stddev: f64, n: usize) -> Summary {
+        Summary {
+            n,
+            mean,
+            stddev,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn welch_p_values() {
+        // df = 2, where p = 1 - t / sqrt(t^2 + 2).
+        let p = welch_t_test(&summary(1.0, 1.0, 2), &summary(0.0, 1.0, 2)).unwrap();
+        assert!((p - (1.0 - 1.0 / 3f64.sqrt())).abs() < 1e-9, "{}", p);
+        // Against the regularized incomplete beta function of mpmath.
+        let p = welch_t_test(&summary(10.0, 1.0, 10), &summary(11.0, 2.0, 12)).unwrap();
+        assert!((p - 0.147387148512962).abs() < 1e-9, "{}", p);
+        let p = welch_t_test(&summary(100.0, 5.0, 5), &summary(90.0, 3.0, 5)).unwrap();
+        assert!((p - 0.007284286281043).abs() < 1e-9, "{}", p);
+        // Symmetric in its arguments.
+        let q = welch_t_test(&summary(90.0, 3.0, 5), &summary(100.0, 5.0, 5)).unwrap();
+        assert!((p - q).abs() < 1e-12);
+    }
+
+    #[test]
+    fn welch_edge_cases() {
+        assert_eq!(welch_t_test(&summary(1.0, 1.0, 1), &summary(2.0, 1.0, 5)), None);
+        assert_eq!(welch_t_test(&summary(1.0, 0.0, 3), &summary(1.0, 0.0, 3)), Some(1.0));
+        assert_eq!(welch_t_test(&summary(1.0, 0.0, 3), &summary(2.0, 0.0, 3)), Some(0.0));
+        let p = welch_t_test(&summary(5.0, 1.0, 30), &summary(5.0, 1.0, 30)).unwrap();
+        assert!((p - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn percentile_interpolates() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert!(close(percentile(&sorted, 0.0), 1.0));
+        assert!(close(percentile(&sorted, 0.5), 2.5));
+        assert!(close(percentile(&sorted, 0.25), 1.75));
+        assert!(close(percentile(&sorted, 1.0), 4.0));
+    }
+
+    #[test]
+    fn outliers_outside_the_fences() {
+        assert_eq!(outliers(&[10.0, 11.0, 10.5, 10.2, 30.0, 10.8]), [4]);
+        assert!(outliers(&[10.0, 11.0, 10.5, 10.2]).is_empty());
+        // Too few values for fences.
+        assert!(outliers(&[1.0, 100.0, 1000.0]).is_empty());
+    }
+
+    #[test]
+    fn non_finite_values_are_left_out() {
+        let values = [2.0, f64::NAN, 4.0, f64::INFINITY];
+        let s = Summary::new(&values);
+        assert_eq!(s.n, 2);
+        assert!(close(s.mean, 3.0));
+        assert_eq!(outliers(&values), [1, 3]);
+        let values = [10.0, f64::NAN, 11.0, 10.5, 10.2, 30.0];
+        assert_eq!(outliers(&values), [1, 5]);
+        // A sample of nothing in no time.
+        let samples = vec![
+            Sample::new(0, Duration::ZERO),
+            Sample::new(1 << 20, Duration::from_secs(1)),
+        ];
+        let m = Measurement::new(samples, true);
+        assert_eq!(m.throughput.n, 1);
+        assert!(close(m.throughput.mean, 1.0));
+    }
+}
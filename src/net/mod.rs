@@ -0,0 +1,214 @@
+//! TCP throughput between a client that sends its input and a server that
+//! receives it, and the connection helpers of the paired benchmarks.
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::{
+    bench::{Benchmark, BenchmarkArgs, Registry},
+    data::InputArgs,
+    environment::Environment,
+    error::{Error, Result},
+    histogram::Histogram,
+    measure::Sample,
+    progress::Progress,
+};
+
+// How long a client waits for its server to start listening.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn register(registry: &mut Registry) {
+    registry.register::<ServerArgs>("tcp-server", "Receive the data of a TCP client");
+    registry.register::<ClientArgs>("tcp-client", "Send the input to a TCP server");
+    registry.register_pair("tcp", "tcp-server", "tcp-client");
+}
+
+/// Connects to `addr` and retries while nothing listens there yet, so that the
+/// two sides of a pair can be started in any order.
+pub fn connect(addr: &str) -> io::Result<TcpStream> {
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    loop {
+        match TcpStream::connect(addr) {
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            res => return res,
+        }
+    }
+}
+
+// Worker threads use consecutive ports starting at the port of `addr`, one
+// connection per worker.
+pub(crate) fn worker_addr(addr: &str, worker: usize) -> String {
+    match addr.rsplit_once(':') {
+        Some((host, port)) => match port.parse::<u16>() {
+            Ok(port) => format!("{}:{}", host, port as usize + worker),
+            Err(_) => addr.to_string(),
+        },
+        None => addr.to_string(),
+    }
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct ServerArgs {
+    /// Address to listen on for the client.
+    #[clap(long, default_value = "127.0.0.1:9600")]
+    listen: String,
+    /// Bytes received per call.
+    #[clap(long, default_value_t = 1024 * 1024)]
+    buf_len: usize,
+    /// Number of clients served one after another, e.g. one per sweep point.
+    #[clap(long, default_value_t = 1)]
+    clients: usize,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct ClientArgs {
+    /// Address of the TCP server.
+    #[clap(long, default_value = "127.0.0.1:9600")]
+    addr: String,
+    /// File whose content is sent.
+    #[clap(long, default_value = "data/bigfile.log")]
+    path: String,
+    /// Bytes sent per call.
+    #[clap(long, default_value_t = 1024 * 1024)]
+    buf_len: usize,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    data: InputArgs,
+}
+
+pub struct ServerBench {
+    args: ServerArgs,
+    listener: Option<TcpListener>,
+}
+
+impl Benchmark for ServerBench {
+    fn setup(&mut self) -> Result<()> {
+        self.listener = Some(TcpListener::bind(&self.args.listen)?);
+        println!("listen on {}", self.args.listen);
+        Ok(())
+    }
+    // The server is passive: it receives transfers until its client disconnects.
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        let (mut stream, _) = self.listener.as_ref().unwrap().accept()?;
+        let start = Instant::now();
+        let mut buf = vec![0u8; self.args.buf_len];
+        let mut total = 0;
+        loop {
+            let mut header = [0u8; 8];
+            match stream.read_exact(&mut header) {
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                res => res?,
+            }
+            let len = u64::from_le_bytes(header) as usize;
+            let mut left = len;
+            while left > 0 {
+                let n = left.min(buf.len());
+                let n = stream.read(&mut buf[..n])?;
+                if n == 0 {
+                    return Err(Error::Protocol(format!(
+                        "client disconnected {} bytes before the end of a transfer",
+                        left
+                    )));
+                }
+                progress.record(n);
+                left -= n;
+            }
+            total += len;
+            stream.write_all(&(len as u64).to_le_bytes())?;
+        }
+        Ok(Sample::new(total, start.elapsed()))
+    }
+    fn teardown(&mut self) -> Result<()> {
+        self.listener = None;
+        Ok(())
+    }
+    fn max_iterations(&self) -> Option<usize> {
+        Some(self.args.clients)
+    }
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.listen = worker_addr(&self.args.listen, worker);
+    }
+}
+
+pub struct ClientBench {
+    args: ClientArgs,
+    data: Vec<u8>,
+    stream: Option<TcpStream>,
+}
+
+impl Benchmark for ClientBench {
+    fn setup(&mut self) -> Result<()> {
+        self.data = self.args.data.load(&self.args.path, None)?;
+        self.stream = Some(connect(&self.args.addr)?);
+        Ok(())
+    }
+    // Every pass sends the length of the input, then the input, and waits for
+    // the server to acknowledge it.
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        let stream = self.stream.as_mut().unwrap();
+        let mut latency = Histogram::new();
+        let start = Instant::now();
+        let mut total = 0;
+        loop {
+            stream.write_all(&(self.data.len() as u64).to_le_bytes())?;
+            for chunk in self.data.chunks(self.args.buf_len) {
+                let op_start = Instant::now();
+                stream.write_all(chunk)?;
+                latency.record_duration(op_start.elapsed());
+                progress.record(chunk.len());
+            }
+            let mut ack = [0u8; 8];
+            stream.read_exact(&mut ack)?;
+            if u64::from_le_bytes(ack) != self.data.len() as u64 {
+                return Err(Error::Protocol(format!(
+                    "server acknowledged {} of {} bytes",
+                    u64::from_le_bytes(ack),
+                    self.data.len()
+                )));
+            }
+            total += self.data.len();
+            if !progress.repeat() {
+                break;
+            }
+        }
+        Ok(Sample::new(total, start.elapsed()).with_latency(latency))
+    }
+    fn teardown(&mut self) -> Result<()> {
+        self.stream = None;
+        self.data = Vec::new();
+        Ok(())
+    }
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.addr = worker_addr(&self.args.addr, worker);
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_path(&self.args.path);
+    }
+}
+
+impl BenchmarkArgs for ServerArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(ServerBench {
+            args: self,
+            listener: None,
+        })
+    }
+}
+
+impl BenchmarkArgs for ClientArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(ClientBench {
+            args: self,
+            data: Vec::new(),
+            stream: None,
+        })
+    }
+}
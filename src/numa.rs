@@ -0,0 +1,212 @@
+//! CPU pinning and NUMA memory placement of benchmark threads. libc has no
+//! wrappers for the memory policy calls, so they go through syscall.
+use std::{fs, io, mem, ptr};
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+const MPOL_BIND: libc::c_int = 2;
+const MPOL_MF_MOVE: libc::c_uint = 1 << 1;
+// Bits in the node masks passed to the kernel.
+const MAX_NODES: usize = 1024;
+
+#[derive(Args, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PlacementArgs {
+    /// Pin threads to these CPUs, e.g. 0-3,8. Workers get one CPU each, round robin.
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_list: Option<String>,
+    /// Pin threads to the CPUs of this NUMA node and allocate memory there.
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numa_node: Option<usize>,
+    /// Allocate buffers on this NUMA node, overrides the node of --numa-node.
+    #[clap(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mem_node: Option<usize>,
+}
+
+/// Where the threads of a run were placed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Placement {
+    /// CPUs each worker thread is pinned to, empty if threads are not pinned.
+    pub cpus: Vec<Vec<usize>>,
+    pub mem_node: Option<usize>,
+}
+
+impl Placement {
+    pub fn new(args: &PlacementArgs, threads: usize) -> Result<Option<Self>> {
+        let cpus = match (&args.cpu_list, args.numa_node) {
+            (Some(list), _) => {
+                let cpus = parse_cpu_list(list)
+                    .ok_or_else(|| Error::Config(format!("invalid cpu list {}", list)))?;
+                if threads > 1 {
+                    (0..threads).map(|i| vec![cpus[i % cpus.len()]]).collect()
+                } else {
+                    vec![cpus]
+                }
+            }
+            (None, Some(node)) => vec![node_cpus(node)?; threads],
+            (None, None) => Vec::new(),
+        };
+        let mem_node = args.mem_node.or(args.numa_node);
+        if cpus.is_empty() && mem_node.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self { cpus, mem_node }))
+    }
+
+    /// Pins the calling thread and binds its future allocations.
+    pub fn apply(&self, worker: usize) -> Result<()> {
+        if let Some(cpus) = self.cpus.get(worker) {
+            pin_thread(cpus).map_err(|e| context(e, format!("pin to cpus {:?}", cpus)))?;
+        }
+        if let Some(node) = self.mem_node {
+            let mask = node_mask(node)?;
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_set_mempolicy,
+                    MPOL_BIND,
+                    mask.as_ptr(),
+                    MAX_NODES as libc::c_ulong,
+                )
+            };
+            if ret != 0 {
+                let e = io::Error::last_os_error();
+                return Err(context(e, format!("bind memory to node {}", node)).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Moves the pages of an existing buffer to the nodes the calling thread is
+/// bound to. Buffers from malloc may reuse pages that were touched before the
+/// thread was bound, so a new policy alone does not place them.
+pub fn bind_buffer(buf: &mut [u8]) -> io::Result<()> {
+    let mut mode: libc::c_int = 0;
+    let mut mask = [0 as libc::c_ulong; MAX_NODES / 64];
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_get_mempolicy,
+            &mut mode as *mut libc::c_int,
+            mask.as_mut_ptr(),
+            MAX_NODES as libc::c_ulong,
+            ptr::null_mut::<libc::c_void>(),
+            0 as libc::c_ulong,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if mode != MPOL_BIND || buf.is_empty() {
+        return Ok(());
+    }
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = buf.as_ptr() as usize & !(page - 1);
+    let len = buf.as_ptr() as usize + buf.len() - start;
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            start as *mut libc::c_void,
+            len as libc::c_ulong,
+            MPOL_BIND,
+            mask.as_ptr(),
+            MAX_NODES as libc::c_ulong,
+            MPOL_MF_MOVE,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn pin_thread(cpus: &[usize]) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for cpu in cpus {
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// The kernel list format, e.g. `0-3,8,10-11`.
+pub fn parse_cpu_list(s: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for item in s.trim().split(',') {
+        match item.find('-') {
+            Some(i) => {
+                let first: usize = item[..i].parse().ok()?;
+                let last: usize = item[i + 1..].parse().ok()?;
+                if first > last {
+                    return None;
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(item.parse().ok()?),
+        }
+    }
+    if cpus.iter().any(|cpu| *cpu >= libc::CPU_SETSIZE as usize) {
+        return None;
+    }
+    Some(cpus)
+}
+
+pub fn node_cpus(node: usize) -> Result<Vec<usize>> {
+    let path = format!("/sys/devices/system/node/node{}/cpulist", node);
+    let list = fs::read_to_string(&path)
+        .map_err(|e| context(e, format!("numa node {}", node)))?;
+    parse_cpu_list(&list)
+        .filter(|cpus| !cpus.is_empty())
+        .ok_or_else(|| Error::Config(format!("no cpus in {}", path)))
+}
+
+fn node_mask(node: usize) -> Result<[libc::c_ulong; MAX_NODES / 64]> {
+    if node >= MAX_NODES - 1 {
+        return Err(Error::Config(format!("invalid numa node {}", node)));
+    }
+    let mut mask = [0 as libc::c_ulong; MAX_NODES / 64];
+    mask[node / 64] |= 1 << (node % 64);
+    Ok(mask)
+}
+
+fn context(e: io::Error, what: String) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", what, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11"), Some(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert_eq!(parse_cpu_list("5"), Some(vec![5]));
+        // As read from sysfs.
+        assert_eq!(parse_cpu_list("0-1\n"), Some(vec![0, 1]));
+        for s in ["", "a", "3-1", "1,", "-1", "1-", "0-3,x", "4096"] {
+            assert_eq!(parse_cpu_list(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn workers_get_one_cpu_each() {
+        let args = PlacementArgs {
+            cpu_list: Some("2-3".to_string()),
+            ..Default::default()
+        };
+        let placement = Placement::new(&args, 3).unwrap().unwrap();
+        assert_eq!(placement.cpus, [[2], [3], [2]]);
+        let placement = Placement::new(&args, 1).unwrap().unwrap();
+        assert_eq!(placement.cpus, [vec![2, 3]]);
+        assert!(Placement::new(&PlacementArgs::default(), 4).unwrap().is_none());
+    }
+}
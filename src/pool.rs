@@ -0,0 +1,168 @@
+//! Runs one instance of a benchmark per worker thread. Every worker builds its
+//! own benchmark, so files, buffers and queue pairs are never shared, and all
+//! workers start measuring together once every setup is done.
+use std::{
+    cell::RefCell,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Barrier},
+    thread,
+    time::Duration,
+};
+
+use clap::ArgMatches;
+
+use crate::{
+    bench::{Benchmark, Entry},
+    error::{Error, Result},
+    histogram::Histogram,
+    measure::{self, MeasureArgs, Measurement, Sample},
+    numa::Placement,
+    progress::{Progress, Reporter},
+    usage::Usage,
+};
+
+pub struct PoolMeasurement {
+    /// The i-th sample of every worker combined: bytes add up and the duration
+    /// is that of the slowest worker.
+    pub total: Measurement,
+    pub workers: Vec<Measurement>,
+}
+
+impl PoolMeasurement {
+    pub fn new(workers: Vec<Measurement>, drop_outliers: bool) -> Self {
+        let n = workers.iter().map(|w| w.samples.len()).min().unwrap_or(0);
+        let samples = (0..n)
+            .map(|i| {
+                let mut latency = Histogram::new();
+                let mut sync_latency = Histogram::new();
+                let mut usage = Usage::default();
+                let mut residency = Vec::new();
+                let mut bytes = 0;
+                let mut duration = Duration::default();
+                for w in workers.iter() {
+                    bytes += w.samples[i].bytes;
+                    duration = duration.max(w.samples[i].duration);
+                    latency.merge(&w.samples[i].latency);
+                    sync_latency.merge(&w.samples[i].sync_latency);
+                    usage.add(&w.samples[i].usage);
+                    residency.extend(w.samples[i].cache_residency);
+                }
+                // Workers that read the same file see the same cache.
+                let residency = match residency.len() {
+                    0 => None,
+                    n => Some(residency.iter().sum::<f64>() / n as f64),
+                };
+                Sample::new(bytes, duration)
+                    .with_latency(latency)
+                    .with_sync_latency(sync_latency)
+                    .with_usage(usage)
+                    .with_cache_residency(residency)
+            })
+            .collect();
+        Self {
+            total: Measurement::new(samples, drop_outliers),
+            workers,
+        }
+    }
+
+    /// Jain's fairness index of the mean worker throughput, 1 if all workers
+    /// are equally fast and 1/n if a single worker gets everything.
+    pub fn fairness(&self) -> f64 {
+        let means: Vec<f64> = self.workers.iter().map(|w| w.throughput.mean).collect();
+        let sum: f64 = means.iter().sum();
+        let sum_sq: f64 = means.iter().map(|m| m * m).sum();
+        if sum_sq == 0f64 {
+            return 1f64;
+        }
+        sum * sum / (means.len() as f64 * sum_sq)
+    }
+}
+
+impl fmt::Display for PoolMeasurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "total {}", self.total)?;
+        for (i, w) in self.workers.iter().enumerate() {
+            writeln!(f, "worker {:<3} throughput(MB/s): {}", i, w.throughput)?;
+        }
+        write!(f, "fairness: {:.3}", self.fairness())
+    }
+}
+
+pub fn execute(
+    entry: &Entry,
+    matches: &ArgMatches,
+    args: &MeasureArgs,
+    placement: Option<&Placement>,
+) -> Result<PoolMeasurement> {
+    let barrier = Arc::new(Barrier::new(args.threads));
+    // Workers share the counters, so intervals show the aggregate bandwidth.
+    let progress = Progress::new();
+    let reporter = args.report_interval().map(|i| Reporter::start(&progress, i));
+    let handles: Vec<_> = (0..args.threads)
+        .map(|worker| {
+            let entry = *entry;
+            let matches = matches.clone();
+            let args = args.clone();
+            let barrier = barrier.clone();
+            let placement = placement.cloned();
+            let progress = progress.clone();
+            thread::spawn(move || {
+                // Every worker has to reach the barrier, even if its setup failed.
+                let bench = panic::catch_unwind(AssertUnwindSafe(|| {
+                    if let Some(placement) = &placement {
+                        placement.apply(worker)?;
+                    }
+                    setup(&entry, &matches, worker, args.threads)
+                }))
+                .unwrap_or_else(|_| Err(Error::WorkerPanicked { worker, phase: "setup" }));
+                barrier.wait();
+                let bench = RefCell::new(bench?);
+                let limit = bench.borrow().max_iterations();
+                let res = measure::measure(
+                    &args,
+                    limit,
+                    &progress,
+                    || bench.borrow_mut().prepare(),
+                    |p| bench.borrow_mut().run(p),
+                );
+                bench.into_inner().teardown()?;
+                res
+            })
+        })
+        .collect();
+    let mut workers = Vec::with_capacity(handles.len());
+    let mut error = None;
+    for (i, handle) in handles.into_iter().enumerate() {
+        match handle.join() {
+            Ok(Ok(measurement)) => workers.push(measurement),
+            Ok(Err(e)) => error = error.or(Some(e)),
+            Err(_) => {
+                error = error.or(Some(Error::WorkerPanicked { worker: i, phase: "run" }))
+            }
+        }
+    }
+    let intervals = reporter.map(Reporter::finish).unwrap_or_default();
+    match error {
+        Some(e) => Err(e),
+        None => {
+            let mut pool = PoolMeasurement::new(workers, args.drop_outliers);
+            pool.total.intervals = intervals;
+            Ok(pool)
+        }
+    }
+}
+
+fn setup(
+    entry: &Entry,
+    matches: &ArgMatches,
+    worker: usize,
+    workers: usize,
+) -> Result<Box<dyn Benchmark>> {
+    let (mut bench, _) = entry
+        .build(matches)
+        .map_err(|e| Error::Config(e.to_string()))?;
+    bench.set_worker(worker, workers);
+    bench.setup()?;
+    Ok(bench)
+}
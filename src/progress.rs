@@ -0,0 +1,170 @@
+//! Progress of a running benchmark. Workloads count their completed operations
+//! and a reporter thread turns the counts into per-interval bandwidth, so that
+//! throttling or cache exhaustion during a run shows up as a time series.
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Default)]
+struct Counters {
+    bytes: AtomicU64,
+    ops: AtomicU64,
+    // When measuring started, set by the first worker that gets there.
+    start: Mutex<Option<Instant>>,
+}
+
+/// Handed to every iteration of a workload. Workloads record each completed
+/// operation, and repeat their work until the deadline if there is one.
+#[derive(Clone, Default)]
+pub struct Progress {
+    counters: Arc<Counters>,
+    deadline: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The same counters with a deadline.
+    pub fn until(&self, deadline: Instant) -> Self {
+        Self {
+            counters: self.counters.clone(),
+            deadline: Some(deadline),
+        }
+    }
+
+    pub fn record(&self, bytes: usize) {
+        self.counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.counters.ops.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether a time-bounded workload should start another pass.
+    pub fn repeat(&self) -> bool {
+        matches!(self.deadline, Some(deadline) if Instant::now() < deadline)
+    }
+
+    /// Whether a time-bounded workload should stop right away.
+    pub fn expired(&self) -> bool {
+        matches!(self.deadline, Some(deadline) if Instant::now() >= deadline)
+    }
+
+    // Starts the clock of the reporter, warmup iterations are not reported.
+    pub(crate) fn start(&self) {
+        let mut start = self.counters.start.lock().unwrap();
+        if start.is_none() {
+            *start = Some(Instant::now());
+        }
+    }
+
+    fn started(&self) -> Option<Instant> {
+        *self.counters.start.lock().unwrap()
+    }
+
+    fn load(&self) -> (u64, u64) {
+        (
+            self.counters.bytes.load(Ordering::Relaxed),
+            self.counters.ops.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Interval {
+    /// Seconds since measuring started.
+    pub start_s: f64,
+    pub duration_s: f64,
+    pub bytes: u64,
+    pub ops: u64,
+    /// MB/s
+    pub throughput: f64,
+    pub ops_per_sec: f64,
+}
+
+impl Interval {
+    fn new(start: Duration, duration: Duration, bytes: u64, ops: u64) -> Self {
+        let secs = duration.as_secs_f64();
+        Self {
+            start_s: start.as_secs_f64(),
+            duration_s: secs,
+            bytes,
+            ops,
+            throughput: bytes as f64 / (1024f64 * 1024f64) / secs,
+            ops_per_sec: ops as f64 / secs,
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:>7.1}s] throughput(MB/s): {:.3}, ops/s: {:.1}",
+            self.start_s + self.duration_s,
+            self.throughput,
+            self.ops_per_sec
+        )
+    }
+}
+
+/// Prints the progress to stderr every interval and keeps the intervals for
+/// the result.
+pub struct Reporter {
+    stop: mpsc::Sender<()>,
+    handle: thread::JoinHandle<Vec<Interval>>,
+}
+
+impl Reporter {
+    pub fn start(progress: &Progress, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let progress = progress.clone();
+        let handle = thread::spawn(move || report(&progress, interval, &stopped));
+        Self { stop, handle }
+    }
+
+    pub fn finish(self) -> Vec<Interval> {
+        let _ = self.stop.send(());
+        self.handle.join().unwrap_or_default()
+    }
+}
+
+fn report(progress: &Progress, interval: Duration, stopped: &mpsc::Receiver<()>) -> Vec<Interval> {
+    let start = loop {
+        if let Some(start) = progress.started() {
+            break start;
+        }
+        match stopped.recv_timeout(Duration::from_millis(10)) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => return Vec::new(),
+        }
+    };
+    let mut intervals = Vec::new();
+    let (mut last, mut last_bytes, mut last_ops) = (start, 0, 0);
+    loop {
+        let timeout = (last + interval).saturating_duration_since(Instant::now());
+        let done = !matches!(stopped.recv_timeout(timeout), Err(RecvTimeoutError::Timeout));
+        let now = Instant::now();
+        let (bytes, ops) = progress.load();
+        // The last interval is usually partial, skip it if nothing happened.
+        if !done || bytes > last_bytes {
+            let i = Interval::new(last - start, now - last, bytes - last_bytes, ops - last_ops);
+            eprintln!("{}", i);
+            intervals.push(i);
+        }
+        if done {
+            return intervals;
+        }
+        last = now;
+        last_bytes = bytes;
+        last_ops = ops;
+    }
+}
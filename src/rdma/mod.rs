@@ -1,4 +1,59 @@
-mod verbs;
+//! RDMA read and write between a client and a server that exchange queue pair
+//! and buffer information over TCP.
+use std::time::Instant;
+
+use rdma_sys::{ibv_wc, ibv_wc_status};
+
+use crate::{
+    bench::Registry,
+    error::{Error, Result},
+    histogram::Histogram,
+    progress::Progress,
+};
+
+pub mod verbs;
 pub mod read;
 pub mod write;
-pub mod send_recv;
\ No newline at end of file
+
+// Control messages sent over the TCP stream once the queue pairs are connected.
+pub(crate) const DISCONNECT: u32 = 0x10;
+// Asks the peer for the checksum of its buffer, answered with a u64.
+pub(crate) const VERIFY: u32 = 0x20;
+
+pub fn register(registry: &mut Registry) {
+    registry.register::<read::ServerArgs>("rdma-read-server", "Expose a buffer to an RDMA read client");
+    registry.register::<read::ClientArgs>("rdma-read-client", "RDMA read the buffer of a read server");
+    registry.register::<write::ServerArgs>("rdma-write-server", "RDMA write a file into a write client");
+    registry.register::<write::ClientArgs>("rdma-write-client", "Receive RDMA writes from a write server");
+    registry.register_pair("rdma-read", "rdma-read-server", "rdma-read-client");
+    registry.register_pair("rdma-write", "rdma-write-server", "rdma-write-client");
+}
+
+
+// Records the post-to-completion latency and the progress of each completed
+// work request. `posted` holds the post time and length of every work request,
+// indexed by wr_id.
+pub(crate) fn record_completions(
+    latency: &mut Histogram,
+    progress: &Progress,
+    posted: &[(Instant, usize)],
+    wcs: &[ibv_wc],
+) {
+    for wc in wcs {
+        if let Some((post_time, len)) = posted.get(wc.wr_id as usize) {
+            latency.record_duration(post_time.elapsed());
+            progress.record(*len);
+        }
+    }
+}
+
+// Fails on the first work request that did not complete successfully.
+pub(crate) fn check_completions(wcs: &[ibv_wc]) -> Result<()> {
+    match wcs.iter().find(|wc| wc.status != ibv_wc_status::IBV_WC_SUCCESS) {
+        Some(wc) => Err(Error::Protocol(format!(
+            "work request {} completed with status {}",
+            wc.wr_id, wc.status
+        ))),
+        None => Ok(()),
+    }
+}
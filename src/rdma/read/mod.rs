@@ -1,22 +1,175 @@
 mod r_client;
 mod r_server;
 
+use std::{net::TcpListener, time::Instant};
 
-use r_server::Rserver;
+use clap::Args;
+use serde::Serialize;
+
+use crate::{
+    bench::{Benchmark, BenchmarkArgs},
+    data::InputArgs,
+    environment::Environment,
+    error::Result,
+    measure::{Measurement, Sample},
+    net::worker_addr,
+    progress::Progress,
+};
 use r_client::Rclient;
-pub fn test_rserver(listen_addr: &str, in_path: &str) {
-    let stream = Rserver::listen_one(listen_addr);
-
-    let mut rserver = Rserver::new(stream, in_path, 1024);
-    let res = rserver.wait_for_disconnect().unwrap();
-    if res == true {
-        println!("disconnect protocol success");
-    } else {
-        println!("disconnect protocol fail");
-    }
-}
-pub fn test_rclient(dst: &str){
-    let mut rclient = Rclient::connect(dst, 1024*1024*20 , 2048);
-    rclient.read_data(1024*1024*2);
-    rclient.disconnect();
-}
\ No newline at end of file
+use r_server::Rserver;
+
+#[derive(Args, Serialize, Debug)]
+pub struct ServerArgs {
+    /// Address to listen on for the client.
+    #[clap(long, default_value = "127.0.0.1:9500")]
+    listen: String,
+    /// File copied into the registered buffer.
+    #[clap(long, default_value = "data/bigfile.log")]
+    path: String,
+    /// RDMA device name.
+    #[clap(long, default_value = "mlx5_1")]
+    dev: String,
+    /// Size of the registered buffer exposed to the client.
+    #[clap(long, default_value_t = 1024 * 1024 * 20)]
+    buf_size: usize,
+    #[clap(long, default_value_t = 1024)]
+    max_cqe: i32,
+    /// Number of clients served one after another, e.g. one per sweep point.
+    #[clap(long, default_value_t = 1)]
+    clients: usize,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    data: InputArgs,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct ClientArgs {
+    /// Address of the read server.
+    #[clap(long, default_value = "127.0.0.1:9500")]
+    addr: String,
+    /// RDMA device name.
+    #[clap(long, default_value = "mlx5_1")]
+    dev: String,
+    /// Size of the local receive buffer.
+    #[clap(long, default_value_t = 1024 * 1024 * 20)]
+    buf_size: usize,
+    #[clap(long, default_value_t = 2048)]
+    max_cqe: i32,
+    /// Bytes read per work request.
+    #[clap(long, default_value_t = 1024 * 1024 * 2)]
+    batch_size: usize,
+    /// Compare the checksum of the read data with the server buffer.
+    #[clap(long)]
+    verify: bool,
+}
+
+pub struct ServerBench {
+    args: ServerArgs,
+    listener: Option<TcpListener>,
+    content: Vec<u8>,
+    failed_disconnects: usize,
+}
+
+impl Benchmark for ServerBench {
+    // The listener stays bound so that clients can connect back to back.
+    fn setup(&mut self) -> Result<()> {
+        self.content = self.args.data.load(&self.args.path, Some(self.args.buf_size))?;
+        self.listener = Some(TcpListener::bind(&self.args.listen)?);
+        println!("listen on {}", self.args.listen);
+        Ok(())
+    }
+    // The server is passive: it serves one client and waits for it to finish reading.
+    fn run(&mut self, _progress: &Progress) -> Result<Sample> {
+        let (stream, _) = self.listener.as_ref().unwrap().accept()?;
+        let start = Instant::now();
+        let mut server = Rserver::new(
+            stream,
+            &self.content,
+            &self.args.dev,
+            self.args.buf_size,
+            self.args.max_cqe,
+        )?;
+        if !server.wait_for_disconnect()? {
+            self.failed_disconnects += 1;
+        }
+        Ok(Sample::new(0, start.elapsed()))
+    }
+    fn teardown(&mut self) -> Result<()> {
+        self.listener = None;
+        self.content = Vec::new();
+        Ok(())
+    }
+    fn report(&self, _measurement: &Measurement) {
+        if self.failed_disconnects == 0 {
+            println!("disconnect protocol success");
+        } else {
+            println!("disconnect protocol fail");
+        }
+    }
+    fn max_iterations(&self) -> Option<usize> {
+        Some(self.args.clients)
+    }
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.listen = worker_addr(&self.args.listen, worker);
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_rdma_device(&self.args.dev);
+    }
+}
+
+pub struct ClientBench {
+    args: ClientArgs,
+    client: Option<Rclient>,
+}
+
+impl Benchmark for ClientBench {
+    fn setup(&mut self) -> Result<()> {
+        self.client = Some(Rclient::connect(
+            &self.args.addr,
+            &self.args.dev,
+            self.args.buf_size,
+            self.args.max_cqe,
+        )?);
+        Ok(())
+    }
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        let client = self.client.as_mut().unwrap();
+        let sample = client.read_data(self.args.batch_size, progress)?;
+        if self.args.verify {
+            client.verify()?;
+        }
+        Ok(sample)
+    }
+    fn teardown(&mut self) -> Result<()> {
+        if let Some(mut client) = self.client.take() {
+            client.disconnect()?;
+        }
+        Ok(())
+    }
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.addr = worker_addr(&self.args.addr, worker);
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_rdma_device(&self.args.dev);
+    }
+}
+
+impl BenchmarkArgs for ServerArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(ServerBench {
+            args: self,
+            listener: None,
+            content: Vec::new(),
+            failed_disconnects: 0,
+        })
+    }
+}
+
+impl BenchmarkArgs for ClientArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(ClientBench {
+            args: self,
+            client: None,
+        })
+    }
+}
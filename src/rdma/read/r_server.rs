@@ -1,79 +1,82 @@
 use std::{
     convert::TryInto,
-    fs::File,
-    io::{BufReader, Read, Seek, Write},
-    net::{TcpListener, TcpStream},
+    io::{self, Read, Write},
+    net::TcpStream,
 };
 
 use rand::Rng;
 use rdma_sys::ibv_access_flags;
 
-use crate::rdma::verbs::{IbvContext, IbvCq, IbvMr, IbvPd, IbvQp};
+use crate::{
+    checksum::checksum,
+    error::Result,
+    numa,
+    rdma::{
+        verbs::{IbvContext, IbvCq, IbvMr, IbvPd, IbvQp},
+        DISCONNECT, VERIFY,
+    },
+};
 
 // one-to-one client/server
 pub(crate) struct Rserver {
     stream: TcpStream,
+    // The client reads from the buffer, the server only holds these.
+    #[allow(dead_code)]
     mr: IbvMr,
+    #[allow(dead_code)]
     qp: IbvQp,
+    #[allow(dead_code)]
     cq: IbvCq,
+    #[allow(dead_code)]
     pd: IbvPd,
+    #[allow(dead_code)]
     context: IbvContext,
     data_buf: Box<[u8]>,
-    max_cqe: i32,
 }
 
 impl Rserver {
-    pub fn listen_one(listen_addr: &str) -> TcpStream {
-        let listener = TcpListener::bind(listen_addr).unwrap();
-        println!("listen on {}", listen_addr);
-        loop {
-            for s in listener.incoming() {
-                let stream = s.unwrap();
-                return stream;
-            }
-        }
-    }
-    pub fn new(mut stream: TcpStream, in_path: &str, max_cqe: i32) -> Self {
-        // read all the data to data buf
-        let mut file = BufReader::new(File::open(in_path).expect("Unable to open input file"));
+    pub fn new(
+        mut stream: TcpStream,
+        content: &[u8],
+        dev: &str,
+        buf_size: usize,
+        max_cqe: i32,
+    ) -> Result<Self> {
         // let mut data_buf =  Vec::new();
         // file.read_to_end(&mut data_buf).unwrap();
         // let mut data_buf = data_buf.into_boxed_slice();
-        let buf_size = 1024*1024*20; 
         let ptr = unsafe {libc::malloc(buf_size) as *mut u8};
-        if ptr == std::ptr::null_mut(){
-            panic!("malloc fail");
+        if ptr.is_null() {
+            return Err(io::Error::other("malloc fail").into());
         }
         let mut data_buf = unsafe {Vec::from_raw_parts(ptr, buf_size, buf_size).into_boxed_slice()};
+        numa::bind_buffer(&mut data_buf)?;
+        let len = content.len().min(buf_size);
+        data_buf[..len].copy_from_slice(&content[..len]);
+        data_buf[len..].iter_mut().for_each(|b| *b = 0);
         // let mut data_buf = Box::new(vec![0_u8; 1024*1024*20]).into_boxed_slice();
         // init rdma connection
         let access_flag = ibv_access_flags::IBV_ACCESS_LOCAL_WRITE
             | ibv_access_flags::IBV_ACCESS_REMOTE_READ
             | ibv_access_flags::IBV_ACCESS_REMOTE_WRITE;
-        let context = IbvContext::new(Some("mlx5_1")).unwrap();
-        let pd = IbvPd::new(&context).unwrap();
+        let context = IbvContext::new(Some(dev))?;
+        let pd = IbvPd::new(&context)?;
 
-        let mr = IbvMr::new(&pd, &mut data_buf, access_flag).unwrap();
-        let cq = IbvCq::new(&context, max_cqe).unwrap();
-        let qp = IbvQp::new(&pd, &cq, &cq, 1, max_cqe as u32, max_cqe as u32, 1, 1, 10).unwrap();
-        qp.modify_reset2init(1).unwrap();
+        let mr = IbvMr::new(&pd, &mut data_buf, access_flag)?;
+        let cq = IbvCq::new(&context, max_cqe)?;
+        let qp = IbvQp::new(&pd, &cq, &cq, 1, max_cqe as u32, max_cqe as u32, 1, 1, 10)?;
+        qp.modify_reset2init(1)?;
 
         let my_qpn = qp.get_qpn();
         let my_psn = rand::thread_rng().gen::<u32>();
-        let my_lid = context.get_lid(1).unwrap();
-        stream.write_all(&my_qpn.to_le_bytes()).unwrap();
-        stream.write_all(&my_psn.to_le_bytes()).unwrap();
-        stream.write_all(&my_lid.to_le_bytes()).unwrap();
-        stream.flush().unwrap();
+        let my_lid = context.get_lid(1)?;
+        stream.write_all(&my_qpn.to_le_bytes())?;
+        stream.write_all(&my_psn.to_le_bytes())?;
+        stream.write_all(&my_lid.to_le_bytes())?;
+        stream.flush()?;
         println!("my_qpn: {}, my_psn: {}, my_lid: {}", my_qpn, my_psn, my_lid);
-        let mut buf = [0u8; 1024];
-        let mut data_size = 10;
-        let mut meta_data: Vec<u8> = vec![];
-        while data_size > 0 {
-            let len = stream.read(&mut buf).unwrap();
-            meta_data.extend_from_slice(&buf[0..len]);
-            data_size -= len;
-        }
+        let mut meta_data = vec![0u8; 10];
+        stream.read_exact(&mut meta_data)?;
         let remote_qpn = u32::from_le_bytes(meta_data[0..4].try_into().unwrap());
         let remote_psn = u32::from_le_bytes(meta_data[4..8].try_into().unwrap());
         let remote_lid = u16::from_le_bytes(meta_data[8..10].try_into().unwrap());
@@ -81,25 +84,22 @@ impl Rserver {
             "remote_qpn: {}, remote_psn: {}, remote_lid: {}",
             remote_qpn, remote_psn, remote_lid
         );
-        qp.modify_init2rtr(0, 1, remote_qpn, remote_psn, remote_lid)
-            .unwrap();
+        qp.modify_init2rtr(0, 1, remote_qpn, remote_psn, remote_lid)?;
         println!("init2rtr");
-        qp.modify_rtr2rts(my_psn).unwrap();
+        qp.modify_rtr2rts(my_psn)?;
         //send addr, len, rkey
         stream
-            .write_all(&(data_buf.as_ptr() as u64).to_le_bytes())
-            .unwrap();
+            .write_all(&(data_buf.as_ptr() as u64).to_le_bytes())?;
         stream
-            .write_all(&(data_buf.len() as u32).to_le_bytes())
-            .unwrap();
-        stream.write_all(&mr.rkey().to_le_bytes()).unwrap();
+            .write_all(&(data_buf.len() as u32).to_le_bytes())?;
+        stream.write_all(&mr.rkey().to_le_bytes())?;
         println!(
             "my_addr: {}, my_len: {}, my_rkey: {}",
             data_buf.as_ptr() as u64,
             data_buf.len(),
             mr.rkey()
         );
-        Self {
+        Ok(Self {
             stream,
             mr,
             qp,
@@ -107,17 +107,22 @@ impl Rserver {
             pd,
             context,
             data_buf,
-            max_cqe,
-        }
+        })
     }
 
-    pub fn wait_for_disconnect(&mut self) -> Result<bool, std::io::Error>{
+    // Answers checksum requests until the client disconnects.
+    pub fn wait_for_disconnect(&mut self) -> Result<bool> {
         let mut buf = [0u8; 4];
-        self.stream.read_exact(&mut buf)?;
-        if u32::from_le_bytes(buf) == 0x10{
-            Ok(true)
-        }else{
-            Ok(false)
+        loop {
+            self.stream.read_exact(&mut buf)?;
+            match u32::from_le_bytes(buf) {
+                VERIFY => {
+                    let sum = checksum(&self.data_buf);
+                    self.stream.write_all(&sum.to_le_bytes())?;
+                }
+                DISCONNECT => return Ok(true),
+                _ => return Ok(false),
+            }
         }
     }
     
@@ -1,2 +0,0 @@
-mod sr_client;
-mod sr_server;
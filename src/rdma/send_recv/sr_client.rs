@@ -1,3 +0,0 @@
-struct SRclient{
-    
-}
\ No newline at end of file
@@ -1,3 +0,0 @@
-struct SRserver{
-    
-}
\ No newline at end of file
@@ -1,3 +1,5 @@
+//! Thin wrappers around the rdma-sys verbs and RDMA CM calls that release
+//! their resources on drop.
 use rdma_sys::ibv_mtu::IBV_MTU_1024;
 use rdma_sys::ibv_qp_state::IBV_QPS_INIT;
 use rdma_sys::{
@@ -20,23 +22,25 @@ use std::ffi::{CStr, CString};
 use std::net::SocketAddrV4;
 use std::os::raw::{c_int, c_void};
 
+use crate::error::{Error, Result};
+
 #[derive(Clone)]
 pub struct IbvPd {
     pub p_ibv_pd: *mut ibv_pd,
 }
 
 impl IbvPd {
-    pub fn new_with_cm(cm_id: &RdmaCmId) -> Result<IbvPd, &'static str> {
+    pub fn new_with_cm(cm_id: &RdmaCmId) -> Result<IbvPd> {
         let pd = unsafe { ibv_alloc_pd((*cm_id.p_rdma_cm_id).verbs) };
         if pd.is_null() {
-            return Err("alloc pd");
+            return Err(Error::last_verbs("ibv_alloc_pd"));
         }
         Ok(IbvPd { p_ibv_pd: pd })
     }
-    pub fn new(context: &IbvContext) -> Result<IbvPd, &'static str> {
+    pub fn new(context: &IbvContext) -> Result<IbvPd> {
         let pd = unsafe { ibv_alloc_pd(context.p_ibv_context) };
         if pd.is_null() {
-            return Err("ibv_alloc_pd()");
+            return Err(Error::last_verbs("ibv_alloc_pd"));
         }
         Ok(IbvPd { p_ibv_pd: pd })
     }
@@ -45,8 +49,9 @@ impl IbvPd {
 impl Drop for IbvPd {
     fn drop(&mut self) {
         let ret = unsafe { ibv_dealloc_pd(self.p_ibv_pd) };
-        if ret != 0 {
-            panic!("ibv_dealloc_pd() error");
+        // errors cannot be returned from drop
+        if let Err(e) = Error::check_verbs("ibv_dealloc_pd", ret) {
+            eprintln!("{}", e);
         }
     }
 }
@@ -57,15 +62,22 @@ pub struct IbvContext {
 }
 
 impl IbvContext {
-    pub fn new(dev_name: Option<&str>) -> Result<IbvContext, &'static str> {
+    pub fn new(dev_name: Option<&str>) -> Result<IbvContext> {
         let mut num_devs: c_int = 0;
         let dev_list_ptr = unsafe { ibv_get_device_list(&mut num_devs) };
+        if dev_list_ptr.is_null() {
+            return Err(Error::last_verbs("ibv_get_device_list"));
+        }
         // if there isn't any IB device in host
-        debug_assert_ne!(num_devs, 0, "found {} device(s)", num_devs);
+        if num_devs == 0 {
+            unsafe { ibv_free_device_list(dev_list_ptr) };
+            return Err(Error::Config("no RDMA device found".to_string()));
+        }
         let ib_dev = match dev_name {
             None => unsafe { *dev_list_ptr },
             Some(dev_name) => {
-                let dev_name_cstr = CString::new(dev_name).unwrap();
+                let dev_name_cstr = CString::new(dev_name)
+                    .map_err(|_| Error::Config(format!("invalid RDMA device name {:?}", dev_name)))?;
                 let dev_list =
                     unsafe { std::slice::from_raw_parts(dev_list_ptr, num_devs as usize) };
                 let mut tmp_dev = std::ptr::null_mut::<ibv_device>();
@@ -79,14 +91,19 @@ impl IbvContext {
                         }
                     }
                 }
+                if tmp_dev.is_null() {
+                    unsafe { ibv_free_device_list(dev_list_ptr) };
+                    return Err(Error::Config(format!("RDMA device {} not found", dev_name)));
+                }
                 tmp_dev
             }
         };
         // get device handle
         let context = unsafe { ibv_open_device(ib_dev) };
         if context.is_null() {
+            let e = Error::last_verbs("ibv_open_device");
             unsafe { ibv_free_device_list(dev_list_ptr) };
-            return Err("ibv_open_device()");
+            return Err(e);
         }
         // free the device list
         unsafe { ibv_free_device_list(dev_list_ptr) };
@@ -94,15 +111,13 @@ impl IbvContext {
             p_ibv_context: context,
         })
     }
-    pub fn query_device(&self) -> ibv_device_attr {
+    pub fn query_device(&self) -> Result<ibv_device_attr> {
         let mut device_attr = unsafe { std::mem::zeroed::<ibv_device_attr>() };
         let ret = unsafe { ibv_query_device(self.p_ibv_context, &mut device_attr) };
-        if ret == -1 {
-            panic!("ibv_query_device() error");
-        }
-        device_attr
+        Error::check_verbs("ibv_query_device", ret)?;
+        Ok(device_attr)
     }
-    pub fn get_lid(&self, port_num: u8) -> Result<u16, &'static str> {
+    pub fn query_port(&self, port_num: u8) -> Result<ibv_port_attr> {
         let mut port_attr = unsafe { std::mem::zeroed::<ibv_port_attr>() };
         let ret = unsafe {
             ibv_query_port(
@@ -111,19 +126,19 @@ impl IbvContext {
                 &mut port_attr as *mut _ as *mut _compat_ibv_port_attr,
             )
         };
-        if ret == -1 {
-            return Err("ibv_query_port()");
-        }
-        IBV_MTU_1024;
-        Ok(port_attr.lid)
+        Error::check_verbs("ibv_query_port", ret)?;
+        Ok(port_attr)
+    }
+    pub fn get_lid(&self, port_num: u8) -> Result<u16> {
+        Ok(self.query_port(port_num)?.lid)
     }
 }
 
 impl Drop for IbvContext {
     fn drop(&mut self) {
         let ret = unsafe { ibv_close_device(self.p_ibv_context) };
-        if ret == -1 {
-            panic!("ibv_close_device() error");
+        if let Err(e) = Error::check_verbs("ibv_close_device", ret) {
+            eprintln!("{}", e);
         }
     }
 }
@@ -138,7 +153,7 @@ impl IbvMr {
         pd: &IbvPd,
         region: &[u8],
         access_flag: ibv_access_flags,
-    ) -> Result<IbvMr, &'static str> {
+    ) -> Result<IbvMr> {
         let mr = unsafe {
             ibv_reg_mr(
                 pd.p_ibv_pd,
@@ -148,7 +163,7 @@ impl IbvMr {
             )
         };
         if mr.is_null() {
-            return Err("ibv_reg_mr()");
+            return Err(Error::last_verbs("ibv_reg_mr"));
         }
         Ok(IbvMr { p_ibv_mr: mr })
     }
@@ -157,10 +172,10 @@ impl IbvMr {
         region: *mut c_void,
         region_len: usize,
         access_flag: ibv_access_flags,
-    ) -> Result<IbvMr, &'static str> {
+    ) -> Result<IbvMr> {
         let mr = unsafe { ibv_reg_mr(pd.p_ibv_pd, region, region_len, access_flag.0 as c_int) };
         if mr.is_null() {
-            return Err("ibv_reg_mr()");
+            return Err(Error::last_verbs("ibv_reg_mr"));
         }
         Ok(IbvMr { p_ibv_mr: mr })
     }
@@ -175,8 +190,8 @@ impl IbvMr {
 impl Drop for IbvMr {
     fn drop(&mut self) {
         let ret = unsafe { ibv_dereg_mr(self.p_ibv_mr) };
-        if ret == -1 {
-            panic!("ibv_dereg_mr() error");
+        if let Err(e) = Error::check_verbs("ibv_dereg_mr", ret) {
+            eprintln!("{}", e);
         }
     }
 }
@@ -188,10 +203,10 @@ pub struct RdmaEventChannel {
 }
 
 impl RdmaEventChannel {
-    pub fn new() -> Result<RdmaEventChannel, &'static str> {
+    pub fn new() -> Result<RdmaEventChannel> {
         let event_channel = unsafe { rdma_create_event_channel() };
         if event_channel.is_null() {
-            return Err("create rdma_event_channel");
+            return Err(Error::last_verbs("rdma_create_event_channel"));
         }
         let cm_event = std::ptr::null_mut::<rdma_cm_event>();
         Ok(RdmaEventChannel {
@@ -199,7 +214,7 @@ impl RdmaEventChannel {
             p_rdma_cm_event: cm_event,
         })
     }
-    pub fn get_and_ack_cm_event(&self) -> Result<rdma_cm_event, &'static str> {
+    pub fn get_and_ack_cm_event(&self) -> Result<rdma_cm_event> {
         let mut p_rdma_cm_event = std::ptr::null_mut::<rdma_cm_event>();
         let mut ret = unsafe {
             rdma_get_cm_event(
@@ -208,7 +223,7 @@ impl RdmaEventChannel {
             )
         };
         if ret == -1 {
-            return Err("rdma_get_cm_event");
+            return Err(Error::last_verbs("rdma_get_cm_event"));
         }
         let mut event_copy = unsafe { std::mem::zeroed::<rdma_cm_event>() };
         unsafe {
@@ -220,12 +235,12 @@ impl RdmaEventChannel {
         }
         ret = unsafe { rdma_ack_cm_event(p_rdma_cm_event as *mut _) };
         if ret == -1 {
-            return Err("rdma_ack_cm_event");
+            return Err(Error::last_verbs("rdma_ack_cm_event"));
         }
         Ok(event_copy)
     }
-    // blocking function
-    pub fn get_cm_event(&mut self) -> rdma_cm_event {
+    /// blocking function
+    pub fn get_cm_event(&mut self) -> Result<rdma_cm_event> {
         let ret = unsafe {
             rdma_get_cm_event(
                 self.p_rdma_event_channel,
@@ -233,7 +248,7 @@ impl RdmaEventChannel {
             )
         };
         if ret == -1 {
-            panic!("rdma_get_cm_event() error");
+            return Err(Error::last_verbs("rdma_get_cm_event"));
         }
         let mut event_copy = unsafe { std::mem::zeroed::<rdma_cm_event>() };
         unsafe {
@@ -243,14 +258,15 @@ impl RdmaEventChannel {
                 std::mem::size_of::<rdma_cm_event>(),
             );
         }
-        event_copy
+        Ok(event_copy)
     }
 
-    pub fn ack_cm_event(&self) {
+    pub fn ack_cm_event(&self) -> Result<()> {
         let ret = unsafe { rdma_ack_cm_event(self.p_rdma_cm_event as *mut _) };
         if ret == -1 {
-            panic!("rdma_ack_cm_event() error");
+            return Err(Error::last_verbs("rdma_ack_cm_event"));
         }
+        Ok(())
     }
 }
 
@@ -271,7 +287,7 @@ impl RdmaCmId {
     pub fn new(
         event_channel: &RdmaEventChannel,
         ps: rdma_port_space::Type,
-    ) -> Result<RdmaCmId, &'static str> {
+    ) -> Result<RdmaCmId> {
         let mut cm_id = std::ptr::null_mut::<rdma_cm_id>();
         let ret = unsafe {
             rdma_create_id(
@@ -282,7 +298,7 @@ impl RdmaCmId {
             )
         };
         if ret == -1 {
-            return Err("rdma_create_id()");
+            return Err(Error::last_verbs("rdma_create_id"));
         }
         Ok(RdmaCmId {
             p_rdma_cm_id: cm_id,
@@ -293,21 +309,21 @@ impl RdmaCmId {
             p_rdma_cm_id: event.id,
         }
     }
-    pub fn connect(&self, conn_param: Option<&mut rdma_conn_param>) -> Result<(), &'static str> {
+    pub fn connect(&self, conn_param: Option<&mut rdma_conn_param>) -> Result<()> {
         let ret = match conn_param {
             None => unsafe { rdma_connect(self.p_rdma_cm_id, std::ptr::null_mut()) },
             Some(param) => unsafe { rdma_connect(self.p_rdma_cm_id, param as *mut _) },
         };
         if ret == -1 {
-            return Err("rdma_connect()");
+            return Err(Error::last_verbs("rdma_connect"));
         }
         Ok(())
     }
 
-    pub fn disconnect(&self) -> Result<(), &'static str> {
+    pub fn disconnect(&self) -> Result<()> {
         let ret = unsafe { rdma_disconnect(self.p_rdma_cm_id) };
         if ret == -1 {
-            return Err("rdma_disconnect()");
+            return Err(Error::last_verbs("rdma_disconnect"));
         }
         Ok(())
     }
@@ -317,7 +333,7 @@ impl RdmaCmId {
         src_addr: &SocketAddrV4,
         dst_addr: &SocketAddrV4,
         timeout_ms: i32,
-    ) -> Result<(), &'static str> {
+    ) -> Result<()> {
         let mut src_addr_in = unsafe { new_sockaddr_in(src_addr) };
         let mut dst_addr_in = unsafe { new_sockaddr_in(dst_addr) };
         let ret = unsafe {
@@ -329,28 +345,28 @@ impl RdmaCmId {
             )
         };
         if ret == -1 {
-            return Err("rdma_resolve_addr()");
+            return Err(Error::last_verbs("rdma_resolve_addr"));
         }
         Ok(())
     }
 
-    pub fn resolve_route(&self, timeout_ms: i32) -> Result<(), &'static str> {
+    pub fn resolve_route(&self, timeout_ms: i32) -> Result<()> {
         let ret = unsafe { rdma_resolve_route(self.p_rdma_cm_id, timeout_ms) };
         if ret == -1 {
-            return Err("rdma_resolve_route()");
+            return Err(Error::last_verbs("rdma_resolve_route"));
         }
         Ok(())
     }
 
-    pub fn migrate_id(&self, event_channel: &RdmaEventChannel) -> Result<(), &'static str> {
+    pub fn migrate_id(&self, event_channel: &RdmaEventChannel) -> Result<()> {
         let ret = unsafe { rdma_migrate_id(self.p_rdma_cm_id, event_channel.p_rdma_event_channel) };
         if ret == -1 {
-            return Err("rdma_migrate_id()");
+            return Err(Error::last_verbs("rdma_migrate_id"));
         }
         Ok(())
     }
 
-    pub fn bind_addr(&self, listen_addr: &SocketAddrV4) -> Result<(), &'static str> {
+    pub fn bind_addr(&self, listen_addr: &SocketAddrV4) -> Result<()> {
         let mut addr = unsafe { new_sockaddr_in(listen_addr) };
         let ret = unsafe {
             rdma_bind_addr(
@@ -359,31 +375,31 @@ impl RdmaCmId {
             )
         };
         if ret == -1 {
-            return Err("rdma_bind_addr()");
+            return Err(Error::last_verbs("rdma_bind_addr"));
         }
         Ok(())
     }
 
-    pub fn listen(&self, backlog: i32) -> Result<(), &'static str> {
+    pub fn listen(&self, backlog: i32) -> Result<()> {
         let ret = unsafe { rdma_listen(self.p_rdma_cm_id, backlog) };
         if ret == -1 {
-            return Err("rdma_listen()");
+            return Err(Error::last_verbs("rdma_listen"));
         }
         Ok(())
     }
 
-    pub fn accept(&self, conn_param: Option<&mut rdma_conn_param>) -> Result<(), &'static str> {
+    pub fn accept(&self, conn_param: Option<&mut rdma_conn_param>) -> Result<()> {
         let ret = match conn_param {
             None => unsafe { rdma_accept(self.p_rdma_cm_id, std::ptr::null_mut()) },
             Some(param) => unsafe { rdma_accept(self.p_rdma_cm_id, param as *mut _) },
         };
         if ret == -1 {
-            return Err("rdma_accept()");
+            return Err(Error::last_verbs("rdma_accept"));
         }
         Ok(())
     }
 
-    pub fn reject(&self, data: &[u8]) {
+    pub fn reject(&self, data: &[u8]) -> Result<()> {
         let ret = unsafe {
             rdma_reject(
                 self.p_rdma_cm_id,
@@ -392,8 +408,9 @@ impl RdmaCmId {
             )
         };
         if ret == -1 {
-            panic!("rdma_reject() error");
+            return Err(Error::last_verbs("rdma_reject"));
         }
+        Ok(())
     }
 
     pub fn get_local_addr(&self) -> &libc::sockaddr {
@@ -414,7 +431,7 @@ impl RdmaCmId {
         max_recv_wr: u32,
         max_send_sge: u32,
         max_recv_sge: u32,
-    ) -> Result<IbvQp, &'static str> {
+    ) -> Result<IbvQp> {
         let mut qp_init_attr = unsafe { std::mem::zeroed::<ibv_qp_init_attr>() };
         qp_init_attr.qp_type = ibv_qp_type::IBV_QPT_RC;
         qp_init_attr.sq_sig_all = sq_sig_all; // set to 0 to avoid CQE for every SR
@@ -427,7 +444,7 @@ impl RdmaCmId {
         let ret =
             unsafe { rdma_create_qp(self.p_rdma_cm_id, pd.p_ibv_pd, &mut qp_init_attr as *mut _) };
         if ret == -1 {
-            return Err("rdma_create_qp()");
+            return Err(Error::last_verbs("rdma_create_qp"));
         }
         unsafe {
             Ok(IbvQp {
@@ -441,15 +458,15 @@ impl RdmaCmId {
 impl Drop for RdmaCmId {
     fn drop(&mut self) {
         let ret = unsafe { rdma_destroy_id(self.p_rdma_cm_id) };
-        if ret == -1 {
-            panic!("rdma_destroy_id() error");
+        if let Err(e) = Error::check_verbs("rdma_destroy_id", ret) {
+            eprintln!("{}", e);
         }
     }
 }
 
 pub fn get_event_str(cm_event_type: rdma_cm_event_type::Type) -> &'static str {
     let ret = unsafe { CStr::from_ptr(rdma_event_str(cm_event_type)) };
-    ret.to_str().unwrap()
+    ret.to_str().unwrap_or("RDMA_CM_EVENT_UNKNOWN")
 }
 
 #[derive(Clone)]
@@ -458,7 +475,7 @@ pub struct IbvCq {
 }
 
 impl IbvCq {
-    pub fn new(context: &IbvContext, cqe: i32) -> Result<IbvCq, &'static str> {
+    pub fn new(context: &IbvContext, cqe: i32) -> Result<IbvCq> {
         let cq = unsafe {
             ibv_create_cq(
                 context.p_ibv_context,
@@ -469,11 +486,11 @@ impl IbvCq {
             )
         };
         if cq.is_null() {
-            return Err("ibv_create_cq()");
+            return Err(Error::last_verbs("ibv_create_cq"));
         }
         Ok(IbvCq { p_ibv_cq: cq })
     }
-    pub fn new_with_cm(cm_id: &RdmaCmId, cqe: i32) -> Result<IbvCq, &'static str> {
+    pub fn new_with_cm(cm_id: &RdmaCmId, cqe: i32) -> Result<IbvCq> {
         let cq = unsafe {
             ibv_create_cq(
                 (*cm_id.p_rdma_cm_id).verbs,
@@ -484,25 +501,25 @@ impl IbvCq {
             )
         };
         if cq.is_null() {
-            return Err("ibv_create_cq()");
+            return Err(Error::last_verbs("ibv_create_cq"));
         }
         Ok(IbvCq { p_ibv_cq: cq })
     }
 
-    pub fn poll<'a>(&self, cqe_arr: &'a mut [ibv_wc]) -> &'a [ibv_wc] {
+    pub fn poll<'a>(&self, cqe_arr: &'a mut [ibv_wc]) -> Result<&'a [ibv_wc]> {
         let n = unsafe { ibv_poll_cq(self.p_ibv_cq, cqe_arr.len() as i32, cqe_arr.as_mut_ptr()) };
         if n < 0 {
-            panic!("ibv_poll_cq() error");
+            return Err(Error::last_verbs("ibv_poll_cq"));
         }
-        &mut cqe_arr[0..n as usize]
+        Ok(&mut cqe_arr[0..n as usize])
     }
 }
 
 impl Drop for IbvCq {
     fn drop(&mut self) {
         let ret = unsafe { ibv_destroy_cq(self.p_ibv_cq) };
-        if ret == -1 {
-            panic!("ibv_destroy_cq error");
+        if let Err(e) = Error::check_verbs("ibv_destroy_cq", ret) {
+            eprintln!("{}", e);
         }
     }
 }
@@ -513,21 +530,19 @@ pub struct IbvCompChannel {
 }
 
 impl IbvCompChannel {
-    pub fn new(context: &mut IbvContext) -> IbvCompChannel {
+    pub fn new(context: &mut IbvContext) -> Result<IbvCompChannel> {
         let comp_channel = unsafe { ibv_create_comp_channel(context.p_ibv_context) };
         if comp_channel.is_null() {
-            panic!("ibv_create_comp_channel() error");
+            return Err(Error::last_verbs("ibv_create_comp_channel"));
         }
-        IbvCompChannel {
+        Ok(IbvCompChannel {
             p_ibv_comp_channel: comp_channel,
-        }
+        })
     }
 
-    pub fn req_notify_cq(&self, cq: &IbvCq, solicited_only: i32) {
+    pub fn req_notify_cq(&self, cq: &IbvCq, solicited_only: i32) -> Result<()> {
         let ret = unsafe { ibv_req_notify_cq(cq.p_ibv_cq, solicited_only) };
-        if ret == -1 {
-            panic!("ibv_req_notify_cq() error");
-        }
+        Error::check_verbs("ibv_req_notify_cq", ret)
     }
     // 这个不容易封装。先不用IbvCompChannel吧。之后可以考虑如果使用IbvCompChannel后，就在内部维护IbvCq
     pub fn get_cq_event(&self) -> *mut ibv_cq {
@@ -553,8 +568,8 @@ impl IbvCompChannel {
 impl Drop for IbvCompChannel {
     fn drop(&mut self) {
         let ret = unsafe { ibv_destroy_comp_channel(self.p_ibv_comp_channel) };
-        if ret == -1 {
-            panic!("ibv_destroy_comp_channel() error");
+        if let Err(e) = Error::check_verbs("ibv_destroy_comp_channel", ret) {
+            eprintln!("{}", e);
         }
     }
 }
@@ -575,7 +590,7 @@ impl IbvQp {
         max_send_sge: u32,
         max_recv_sge: u32,
         max_inline_data: u32,
-    ) -> Result<IbvQp, &'static str> {
+    ) -> Result<IbvQp> {
         let mut qp_init_attr = unsafe { std::mem::zeroed::<ibv_qp_init_attr>() };
         qp_init_attr.qp_type = ibv_qp_type::IBV_QPT_RC;
         qp_init_attr.sq_sig_all = sq_sig_all; // set to 0 to avoid CQE for every SR
@@ -588,7 +603,7 @@ impl IbvQp {
         qp_init_attr.cap.max_inline_data = max_inline_data;
         let p_ibv_qp = unsafe { ibv_create_qp(pd.p_ibv_pd, &mut qp_init_attr as *mut _) };
         if p_ibv_qp.is_null() {
-            return Err("ibv_create_qp()");
+            return Err(Error::last_verbs("ibv_create_qp"));
         }
 
         Ok(IbvQp {
@@ -596,7 +611,7 @@ impl IbvQp {
             p_rdma_cm_id: std::ptr::null_mut(),
         })
     }
-    pub fn modify_reset2init(&self, port_num: u8) -> Result<(), &'static str> {
+    pub fn modify_reset2init(&self, port_num: u8) -> Result<()> {
         let mut qp_attr = unsafe { std::mem::zeroed::<ibv_qp_attr>() };
         qp_attr.qp_state = IBV_QPS_INIT;
         qp_attr.pkey_index = 0;
@@ -614,10 +629,7 @@ impl IbvQp {
                     | ibv_qp_attr_mask::IBV_QP_ACCESS_FLAGS.0) as i32,
             )
         };
-        if ret == -1 {
-            return Err("ibv_modify_qp()");
-        }
-        Ok(())
+        Error::check_verbs("ibv_modify_qp", ret)
     }
     pub fn modify_init2rtr(
         &self,
@@ -627,7 +639,7 @@ impl IbvQp {
         remote_qpn: u32,
         remote_psn: u32,
         remote_lid: u16,
-    ) -> Result<(), &'static str> {
+    ) -> Result<()> {
         let mut qp_attr = unsafe { std::mem::zeroed::<ibv_qp_attr>() };
         qp_attr.qp_state = ibv_qp_state::IBV_QPS_RTR;
         qp_attr.path_mtu = IBV_MTU_1024;
@@ -653,13 +665,10 @@ impl IbvQp {
                     | ibv_qp_attr_mask::IBV_QP_MIN_RNR_TIMER.0) as i32,
             )
         };
-        if ret == -1 {
-            return Err("ibv_modify_qp()");
-        }
-        Ok(())
+        Error::check_verbs("ibv_modify_qp", ret)
     }
 
-    pub fn modify_rtr2rts(&self, psn: u32) -> Result<(), &'static str> {
+    pub fn modify_rtr2rts(&self, psn: u32) -> Result<()> {
         let mut qp_attr = unsafe { std::mem::zeroed::<ibv_qp_attr>() };
         qp_attr.qp_state = ibv_qp_state::IBV_QPS_RTS;
         qp_attr.timeout = 14;
@@ -679,10 +688,7 @@ impl IbvQp {
                     | ibv_qp_attr_mask::IBV_QP_MAX_QP_RD_ATOMIC.0) as i32,
             )
         };
-        if ret == -1 {
-            return Err("ibv_modify_qp()");
-        }
-        Ok(())
+        Error::check_verbs("ibv_modify_qp", ret)
     }
     pub fn get_qpn(&self) -> u32 {
         unsafe { (*self.p_ibv_qp).qp_num }
@@ -692,8 +698,8 @@ impl Drop for IbvQp {
     fn drop(&mut self) {
         if self.p_rdma_cm_id.is_null() {
             let ret = unsafe { ibv_destroy_qp(self.p_ibv_qp) };
-            if ret == -1 {
-                panic!("ibv_destroy_qp() error");
+            if let Err(e) = Error::check_verbs("ibv_destroy_qp", ret) {
+                eprintln!("{}", e);
             }
         } else {
             unsafe {
@@ -703,7 +709,7 @@ impl Drop for IbvQp {
     }
 }
 
-pub fn post_send(buffer: &[u8], qp: &IbvQp, lkey: u32, wr_id: u64) {
+pub fn post_send(buffer: &[u8], qp: &IbvQp, lkey: u32, wr_id: u64) -> Result<()> {
     let mut bad_wr = std::ptr::null_mut::<ibv_send_wr>();
     let mut sge = ibv_sge {
         addr: buffer.as_ptr() as u64,
@@ -719,12 +725,10 @@ pub fn post_send(buffer: &[u8], qp: &IbvQp, lkey: u32, wr_id: u64) {
     wr.opcode = ibv_wr_opcode::IBV_WR_SEND;
     wr.send_flags = ibv_send_flags::IBV_SEND_SIGNALED.0;
     let ret = unsafe { ibv_post_send(qp.p_ibv_qp, &mut wr as *mut _, &mut bad_wr as *mut _) };
-    if ret == -1 {
-        panic!("post_send() error");
-    }
+    Error::check_verbs("ibv_post_send", ret)
 }
 
-pub fn post_recv(buffer: &[u8], qp: &IbvQp, lkey: u32, wr_id: u64) {
+pub fn post_recv(buffer: &[u8], qp: &IbvQp, lkey: u32, wr_id: u64) -> Result<()> {
     let mut bad_wr = std::ptr::null_mut::<ibv_recv_wr>();
     let mut sge = ibv_sge {
         addr: buffer.as_ptr() as u64,
@@ -737,9 +741,7 @@ pub fn post_recv(buffer: &[u8], qp: &IbvQp, lkey: u32, wr_id: u64) {
     wr.sg_list = &mut sge as *mut _;
     wr.num_sge = 1;
     let ret = unsafe { ibv_post_recv(qp.p_ibv_qp, &mut wr as *mut _, &mut bad_wr as *mut _) };
-    if ret == -1 {
-        panic!("post_recv() error");
-    }
+    Error::check_verbs("ibv_post_recv", ret)
 }
 
 pub fn post_read(
@@ -750,7 +752,7 @@ pub fn post_read(
     mr: &IbvMr,
     wr_id: u64,
     send_flags: u32,
-) {
+) -> Result<()> {
     let ret = post_read_or_write(
         buffer,
         remote_addr,
@@ -761,9 +763,7 @@ pub fn post_read(
         send_flags,
         ibv_wr_opcode::IBV_WR_RDMA_READ,
     );
-    if ret == -1 {
-        panic!("post_write() error");
-    }
+    Error::check_verbs("ibv_post_send", ret)
 }
 
 pub fn post_write(
@@ -774,7 +774,7 @@ pub fn post_write(
     mr: &IbvMr,
     wr_id: u64,
     send_flags: u32,
-) {
+) -> Result<()> {
     let ret = post_read_or_write(
         buffer,
         remote_addr,
@@ -785,9 +785,7 @@ pub fn post_write(
         send_flags,
         ibv_wr_opcode::IBV_WR_RDMA_WRITE,
     );
-    if ret == -1 {
-        panic!("post_write() error");
-    }
+    Error::check_verbs("ibv_post_send", ret)
 }
 pub fn post_write_raw(
     addr: u64,
@@ -798,7 +796,7 @@ pub fn post_write_raw(
     qp: &IbvQp,
     wr_id: u64,
     send_flags: u32,
-) {
+) -> Result<()> {
     let mut bad_wr = std::ptr::null_mut::<ibv_send_wr>();
     let mut sge = ibv_sge {
         addr: addr,
@@ -814,7 +812,8 @@ pub fn post_write_raw(
     wr.wr.rdma.remote_addr = remote_addr;
     wr.wr.rdma.rkey = rkey;
     wr.send_flags = send_flags;
-    let _res = unsafe { ibv_post_send(qp.p_ibv_qp, &mut wr as *mut _, &mut bad_wr as *mut _) };
+    let ret = unsafe { ibv_post_send(qp.p_ibv_qp, &mut wr as *mut _, &mut bad_wr as *mut _) };
+    Error::check_verbs("ibv_post_send", ret)
 }
 
 fn post_read_or_write(
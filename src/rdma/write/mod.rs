@@ -1,17 +1,156 @@
-use self::{w_server::Wserver, w_client::Wclient};
+use std::time::Instant;
+
+use clap::Args;
+use serde::Serialize;
+
+use self::{w_client::Wclient, w_server::Wserver};
+use crate::{
+    bench::{Benchmark, BenchmarkArgs},
+    data::InputArgs,
+    environment::Environment,
+    error::Result,
+    measure::{Measurement, Sample},
+    net::worker_addr,
+    progress::Progress,
+};
 
 mod w_client;
 mod w_server;
 
-pub fn test_wserver(listen_addr: &str, in_path: &str) {
-    let stream = Wserver::listen_one(listen_addr);
+#[derive(Args, Serialize, Debug)]
+pub struct ServerArgs {
+    /// Address to listen on for the client.
+    #[clap(long, default_value = "127.0.0.1:9500")]
+    listen: String,
+    /// File whose content is written to the client.
+    #[clap(long, default_value = "data/bigfile.log")]
+    path: String,
+    /// RDMA device name.
+    #[clap(long, default_value = "mlx5_1")]
+    dev: String,
+    #[clap(long, default_value_t = 1024)]
+    max_cqe: i32,
+    /// Bytes written per work request.
+    #[clap(long, default_value_t = 1024 * 1024)]
+    batch_size: usize,
+    /// Compare the checksum of the client buffer with the written data.
+    #[clap(long)]
+    verify: bool,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    data: InputArgs,
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct ClientArgs {
+    /// Address of the write server.
+    #[clap(long, default_value = "127.0.0.1:9500")]
+    addr: String,
+    /// RDMA device name.
+    #[clap(long, default_value = "mlx5_1")]
+    dev: String,
+    #[clap(long, default_value_t = 1024)]
+    max_cqe: i32,
+    /// File the received data is dumped to.
+    #[clap(long, default_value = "log/rdma_write.log")]
+    out_path: String,
+}
+
+pub struct ServerBench {
+    args: ServerArgs,
+    server: Option<Wserver>,
+}
+
+impl Benchmark for ServerBench {
+    fn setup(&mut self) -> Result<()> {
+        let content = self.args.data.load(&self.args.path, None)?;
+        let stream = Wserver::listen_one(&self.args.listen)?;
+        self.server = Some(Wserver::new(
+            stream,
+            content,
+            &self.args.dev,
+            self.args.max_cqe,
+        )?);
+        Ok(())
+    }
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        let server = self.server.as_mut().unwrap();
+        let sample = server.write_data(self.args.batch_size, progress)?;
+        if self.args.verify {
+            server.verify()?;
+        }
+        Ok(sample)
+    }
+    fn teardown(&mut self) -> Result<()> {
+        if let Some(mut server) = self.server.take() {
+            server.disconnect()?;
+        }
+        Ok(())
+    }
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.listen = worker_addr(&self.args.listen, worker);
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_rdma_device(&self.args.dev);
+    }
+}
+
+pub struct ClientBench {
+    args: ClientArgs,
+    client: Option<Wclient>,
+}
+
+impl Benchmark for ClientBench {
+    fn setup(&mut self) -> Result<()> {
+        self.client = Some(Wclient::connect(
+            &self.args.addr,
+            &self.args.dev,
+            self.args.max_cqe,
+        )?);
+        Ok(())
+    }
+    // The client is passive: it only waits for the server to finish writing.
+    fn run(&mut self, _progress: &Progress) -> Result<Sample> {
+        let start = Instant::now();
+        self.client
+            .as_mut()
+            .unwrap()
+            .wait_for_disconnect(&self.args.out_path)?;
+        Ok(Sample::new(0, start.elapsed()))
+    }
+    fn teardown(&mut self) -> Result<()> {
+        self.client = None;
+        Ok(())
+    }
+    fn report(&self, _measurement: &Measurement) {
+        println!("received data written to {}", self.args.out_path);
+    }
+    fn max_iterations(&self) -> Option<usize> {
+        Some(1)
+    }
+    fn set_worker(&mut self, worker: usize, _workers: usize) {
+        self.args.addr = worker_addr(&self.args.addr, worker);
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_rdma_device(&self.args.dev);
+        env.add_path(&self.args.out_path);
+    }
+}
+
+impl BenchmarkArgs for ServerArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(ServerBench {
+            args: self,
+            server: None,
+        })
+    }
+}
 
-    let mut wserver = Wserver::new(stream, in_path, 1024);
-    wserver.write_data(1024*1024);
-    wserver.disconnect();
-    
+impl BenchmarkArgs for ClientArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(ClientBench {
+            args: self,
+            client: None,
+        })
+    }
 }
-pub fn test_wclient(dst: &str){
-    let mut wclient = Wclient::connect(dst, 1024);
-    wclient.wait_for_disconnect();
-}
\ No newline at end of file
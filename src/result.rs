@@ -0,0 +1,445 @@
+use std::{
+    collections::BTreeMap,
+    ffi::CStr,
+    fmt::Write as _,
+    fs::File,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::{ArgEnum, Args};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    environment::Environment,
+    error::{Error, Result},
+    histogram::{Histogram, PERCENTILES},
+    measure::{MeasureArgs, Measurement, Summary},
+    numa::Placement,
+    pool::PoolMeasurement,
+    progress::Interval,
+    usage::Usage,
+};
+
+/// Benchmark arguments keyed by field name.
+pub type Parameters = BTreeMap<String, serde_json::Value>;
+
+#[derive(ArgEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    // Tables in the format of doc/disk.md and doc/rdma.md.
+    Markdown,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct OutputArgs {
+    /// Result format.
+    #[clap(long, arg_enum, default_value = "text", global = true)]
+    pub output: OutputFormat,
+    /// Write results to this file instead of stdout.
+    #[clap(long, global = true)]
+    pub output_file: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SampleRecord {
+    pub bytes: usize,
+    pub duration_us: f64,
+    /// MB/s
+    pub throughput: f64,
+    /// Fraction of the input in the page cache when the sample started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_residency: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub min_ns: u64,
+    pub mean_ns: f64,
+    pub max_ns: u64,
+    /// (percentile, ns)
+    pub percentiles: Vec<(f64, u64)>,
+    /// (low ns, high ns exclusive, count) of every non-empty bucket
+    pub buckets: Vec<(u64, u64, u64)>,
+}
+
+impl LatencyStats {
+    pub fn new(hist: &Histogram) -> Self {
+        Self {
+            count: hist.count(),
+            min_ns: hist.min(),
+            mean_ns: hist.mean(),
+            max_ns: hist.max(),
+            percentiles: PERCENTILES.iter().map(|p| (*p, hist.percentile(*p))).collect(),
+            buckets: hist.buckets().collect(),
+        }
+    }
+    pub fn percentile(&self, p: f64) -> Option<u64> {
+        self.percentiles.iter().find(|(q, _)| *q == p).map(|(_, v)| *v)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HostInfo {
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    pub cpus: usize,
+    /// Seconds since the unix epoch when the result was recorded.
+    pub timestamp: u64,
+}
+
+impl HostInfo {
+    pub fn collect() -> Self {
+        let mut buf = [0 as libc::c_char; 256];
+        let hostname = unsafe {
+            if libc::gethostname(buf.as_mut_ptr(), buf.len()) == 0 {
+                CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned()
+            } else {
+                String::new()
+            }
+        };
+        let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as usize;
+        Self {
+            hostname,
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            cpus,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BenchResult {
+    /// Name of the scenario the result was run in by run-suite.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suite: Option<String>,
+    pub benchmark: String,
+    pub parameters: Parameters,
+    pub measure: MeasureArgs,
+    pub samples: Vec<SampleRecord>,
+    pub outliers: Vec<usize>,
+    pub total_bytes: usize,
+    pub total_duration_us: f64,
+    /// MB/s
+    pub throughput: Summary,
+    pub latency: Option<LatencyStats>,
+    /// Latency of the calls that made written data durable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_latency: Option<LatencyStats>,
+    /// Timed operations per second, e.g. IOPS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ops_per_sec: Option<f64>,
+    /// CPU time, faults and context switches of all samples.
+    #[serde(default)]
+    pub usage: Usage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mb_per_cpu_s: Option<f64>,
+    /// CPU time per second of measured time, 1 for a busy core.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_utilization: Option<f64>,
+    /// Bandwidth over time when run with --report-interval.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub intervals: Vec<Interval>,
+    /// Per worker throughput and Jain's fairness index when run with --threads.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workers: Vec<Summary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fairness: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placement: Option<Placement>,
+    pub host: HostInfo,
+    #[serde(default)]
+    pub environment: Environment,
+}
+
+impl BenchResult {
+    pub fn new(
+        benchmark: &str,
+        parameters: Parameters,
+        measure: MeasureArgs,
+        measurement: &Measurement,
+    ) -> Self {
+        let samples = measurement
+            .samples
+            .iter()
+            .map(|s| SampleRecord {
+                bytes: s.bytes,
+                duration_us: s.duration.as_secs_f64() * 1e6,
+                throughput: s.throughput(),
+                cache_residency: s.cache_residency,
+            })
+            .collect();
+        let latency = if measurement.latency.is_empty() {
+            None
+        } else {
+            Some(LatencyStats::new(&measurement.latency))
+        };
+        Self {
+            suite: None,
+            benchmark: benchmark.to_string(),
+            parameters,
+            measure,
+            samples,
+            outliers: measurement.outliers.clone(),
+            total_bytes: measurement.total_bytes(),
+            total_duration_us: measurement.total_duration().as_secs_f64() * 1e6,
+            throughput: measurement.throughput,
+            latency,
+            sync_latency: if measurement.sync_latency.is_empty() {
+                None
+            } else {
+                Some(LatencyStats::new(&measurement.sync_latency))
+            },
+            ops_per_sec: measurement.ops_per_sec(),
+            usage: measurement.usage,
+            mb_per_cpu_s: measurement.mb_per_cpu_s(),
+            cpu_utilization: measurement.cpu_utilization(),
+            intervals: measurement.intervals.clone(),
+            workers: Vec::new(),
+            fairness: None,
+            placement: None,
+            host: HostInfo::collect(),
+            environment: Environment::default(),
+        }
+    }
+
+    pub fn with_workers(mut self, pool: &PoolMeasurement) -> Self {
+        self.workers = pool.workers.iter().map(|w| w.throughput).collect();
+        self.fairness = Some(pool.fairness());
+        self
+    }
+
+    pub fn with_placement(mut self, placement: Option<Placement>) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    pub fn with_environment(mut self, environment: Environment) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    pub fn with_suite(mut self, suite: Option<String>) -> Self {
+        self.suite = suite;
+        self
+    }
+
+    pub fn parameter_string(&self) -> String {
+        parameter_string(&self.parameters)
+    }
+}
+
+/// `key=value` pairs separated by spaces.
+pub fn parameter_string(parameters: &Parameters) -> String {
+    parameters
+        .iter()
+        .map(|(k, v)| match v {
+            serde_json::Value::String(s) => format!("{}={}", k, s),
+            v => format!("{}={}", k, v),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn render(results: &[BenchResult], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Text => render_text(results),
+        OutputFormat::Json => serde_json::to_string_pretty(results).unwrap() + "\n",
+        OutputFormat::Csv => render_csv(results),
+        OutputFormat::Markdown => render_markdown(results),
+    }
+}
+
+pub fn write_results(results: &[BenchResult], args: &OutputArgs) -> Result<()> {
+    let out = render(results, args.output);
+    match &args.output_file {
+        Some(path) => File::create(path)?.write_all(out.as_bytes())?,
+        None => io::stdout().write_all(out.as_bytes())?,
+    }
+    Ok(())
+}
+
+pub fn load_results(path: &str) -> Result<Vec<BenchResult>> {
+    let file = File::open(path)?;
+    serde_json::from_reader(io::BufReader::new(file))
+        .map_err(|e| Error::Config(format!("results {}: {}", path, e)))
+}
+
+fn render_text(results: &[BenchResult]) -> String {
+    let mut out = String::new();
+    for r in results {
+        writeln!(out, "{} {}", r.benchmark, r.parameter_string()).unwrap();
+        write!(out, "throughput(MB/s): {}", r.throughput).unwrap();
+        if !r.outliers.is_empty() {
+            write!(out, ", {} outlier(s)", r.outliers.len()).unwrap();
+        }
+        out.push('\n');
+        if let Some(ops) = r.ops_per_sec {
+            writeln!(out, "ops/s: {:.1}", ops).unwrap();
+        }
+        if let Some(latency) = &r.latency {
+            write_latency(&mut out, "latency", latency);
+        }
+        if let Some(latency) = &r.sync_latency {
+            write_latency(&mut out, "sync latency", latency);
+        }
+        if let (Some(mb), Some(cores)) = (r.mb_per_cpu_s, r.cpu_utilization) {
+            writeln!(out, "MB per cpu-second: {:.3}, cores busy: {:.2}", mb, cores).unwrap();
+        }
+        writeln!(out, "{}", r.usage).unwrap();
+        if r.samples.iter().any(|s| s.cache_residency.is_some()) {
+            write!(out, "page cache residency(%):").unwrap();
+            for s in r.samples.iter() {
+                match s.cache_residency {
+                    Some(res) => write!(out, " {:.1}", res * 100f64).unwrap(),
+                    None => write!(out, " -").unwrap(),
+                }
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn write_latency(out: &mut String, name: &str, latency: &LatencyStats) {
+    write!(out, "{}(us):", name).unwrap();
+    for (p, v) in latency.percentiles.iter() {
+        write!(out, " p{} {:.3},", p, *v as f64 / 1e3).unwrap();
+    }
+    writeln!(out, " max {:.3} (n={})", latency.max_ns as f64 / 1e3, latency.count).unwrap();
+}
+
+// One table per benchmark with a column for every parameter that differs
+// between its runs, e.g.
+//
+// buf_len(B) | throughput(MB/s)
+// --------|-----------
+// 1024 | 1233.026
+fn render_markdown(results: &[BenchResult]) -> String {
+    let mut benchmarks: Vec<&str> = Vec::new();
+    for r in results {
+        if !benchmarks.contains(&r.benchmark.as_str()) {
+            benchmarks.push(&r.benchmark);
+        }
+    }
+    let mut out = String::new();
+    for name in benchmarks {
+        let runs: Vec<&BenchResult> = results.iter().filter(|r| r.benchmark == name).collect();
+        let swept: Vec<&String> = runs[0]
+            .parameters
+            .keys()
+            .filter(|k| runs.iter().any(|r| r.parameters.get(*k) != runs[0].parameters.get(*k)))
+            .collect();
+        writeln!(out, "* {}\n", name).unwrap();
+        let mut header: Vec<String> = swept
+            .iter()
+            .map(|k| {
+                if k.ends_with("_len") || k.ends_with("_size") {
+                    format!("{}(B)", k)
+                } else {
+                    k.to_string()
+                }
+            })
+            .collect();
+        header.push("throughput(MB/s)".to_string());
+        writeln!(out, "{}", header.join(" | ")).unwrap();
+        let mut separator = vec!["--------"; header.len() - 1];
+        separator.push("-----------");
+        writeln!(out, "{}", separator.join("|")).unwrap();
+        for r in runs {
+            let mut row: Vec<String> = swept
+                .iter()
+                .map(|k| match &r.parameters[*k] {
+                    serde_json::Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                })
+                .collect();
+            row.push(format!("{:.3}", r.throughput.mean));
+            writeln!(out, "{}", row.join(" | ")).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+const CSV_HEADER: &str = "benchmark,parameters,samples,total_bytes,total_duration_us,\
+throughput_mean,throughput_median,throughput_stddev,throughput_min,throughput_max,\
+throughput_ci95_low,throughput_ci95_high,latency_p50_ns,latency_p90_ns,latency_p99_ns,\
+latency_p99_9_ns,latency_max_ns,hostname,timestamp,cpu_user_s,cpu_system_s,mb_per_cpu_s,\
+cpu_utilization,minor_faults,major_faults,voluntary_switches,involuntary_switches,max_rss_kb,ops_per_sec,\
+sync_latency_p50_ns,sync_latency_p90_ns,sync_latency_p99_ns,sync_latency_p99_9_ns,sync_latency_max_ns";
+
+fn render_csv(results: &[BenchResult]) -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push('\n');
+    for r in results {
+        let t = &r.throughput;
+        let mut latency = String::new();
+        for p in PERCENTILES.iter() {
+            let v = r.latency.as_ref().and_then(|l| l.percentile(*p));
+            latency += &format!("{},", v.map(|v| v.to_string()).unwrap_or_default());
+        }
+        let max = r.latency.as_ref().map(|l| l.max_ns.to_string()).unwrap_or_default();
+        let mut sync_latency = String::new();
+        for p in PERCENTILES.iter() {
+            let v = r.sync_latency.as_ref().and_then(|l| l.percentile(*p));
+            sync_latency += &format!("{},", v.map(|v| v.to_string()).unwrap_or_default());
+        }
+        let sync_max = r.sync_latency.as_ref().map(|l| l.max_ns.to_string()).unwrap_or_default();
+        writeln!(
+            out,
+            "{},{},{},{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{}{},{},{},{:.3},{:.3},{},{},{},{},{},{},{},{},{}{}",
+            csv_field(&r.benchmark),
+            csv_field(&r.parameter_string()),
+            r.samples.len(),
+            r.total_bytes,
+            r.total_duration_us,
+            t.mean,
+            t.median,
+            t.stddev,
+            t.min,
+            t.max,
+            t.ci95.0,
+            t.ci95.1,
+            latency,
+            max,
+            csv_field(&r.host.hostname),
+            r.host.timestamp,
+            r.usage.user_s,
+            r.usage.system_s,
+            fmt_opt(r.mb_per_cpu_s),
+            fmt_opt(r.cpu_utilization),
+            r.usage.minor_faults,
+            r.usage.major_faults,
+            r.usage.voluntary_switches,
+            r.usage.involuntary_switches,
+            r.usage.max_rss_kb,
+            fmt_opt(r.ops_per_sec),
+            sync_latency,
+            sync_max
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn fmt_opt(v: Option<f64>) -> String {
+    v.map(|v| format!("{:.3}", v)).unwrap_or_default()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
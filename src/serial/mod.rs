@@ -1 +1,8 @@
-pub mod serialize;
\ No newline at end of file
+//! Serialization of the input into length-prefixed batches.
+use crate::bench::Registry;
+
+pub mod serialize;
+
+pub fn register(registry: &mut Registry) {
+    registry.register::<serialize::SerializeArgs>("serial", "Length-prefixed batch serialization into BytesMut");
+}
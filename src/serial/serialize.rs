@@ -1,52 +1,127 @@
-use std::{fs::File, io::Read, time::SystemTime};
+use std::{
+    fs::File,
+    io::Read,
+    time::{Instant, SystemTime},
+};
 use bytes::BufMut;
 use bytes::BytesMut;
-use bytes::Bytes;
-pub fn bincode_serialize<T: serde::ser::Serialize>(data: &[u8], batch_size: usize) {
-    println!("func start");
+use clap::Args;
+use serde::Serialize;
+
+use crate::{
+    bench::{Benchmark, BenchmarkArgs},
+    data::InputArgs,
+    environment::Environment,
+    error::Result,
+    histogram::Histogram,
+    measure::Sample,
+    progress::Progress,
+};
+
+/// Appends `data` to a `BytesMut` in length-prefixed batches of `batch_size` bytes.
+pub fn bincode_serialize<T: serde::ser::Serialize>(
+    data: &[u8],
+    batch_size: usize,
+    progress: &Progress,
+) -> Sample {
     let mut s_batch = 0;
     let mut e_batch = s_batch + batch_size;
     let start = SystemTime::now();
     let mut total_size = 0;
+    let mut latency = Histogram::new();
     // let mut buf = Vec::with_capacity(data.len());
-    println!("test begin");
     let mut buf = BytesMut::with_capacity(1024);
-    for _i in 0..(data.len() / batch_size) {
-        // let serialized_data =
-        //     bincode::serialize(&data[s_batch..e_batch]).expect("can not serialized");
+    loop {
+        for _i in 0..(data.len() / batch_size) {
+            // let serialized_data =
+            //     bincode::serialize(&data[s_batch..e_batch]).expect("can not serialized");
         
-        // bincode::serialize_into(&mut buf, &data[s_batch..e_batch]).unwrap();
-        let d = &data[s_batch..e_batch];
-        buf.put_u32_le(d.len() as u32);
-        buf.put(&d[..]);
+            // bincode::serialize_into(&mut buf, &data[s_batch..e_batch]).unwrap();
+            let op_start = Instant::now();
+            let d = &data[s_batch..e_batch];
+            buf.put_u32_le(d.len() as u32);
+            buf.put(&d[..]);
         
-        let ser_buf = buf.split();
-        let s = ser_buf.freeze();
-        let x = s.len();
-        // buf.extend_from_slice(&d[..]);
-        // buf.extend_from_slice(extend)
-        // let ser_buf = buf.split();
-        // println!("len: {}, capacity: {}", ser_buf.len(), ser_buf.capacity());
-        // break;
-        // buf.extend_from_slice(&d.len().to_le_bytes());
-        // buf.extend_from_slice(&d[..]);
-        s_batch += batch_size;
-        e_batch += batch_size;
-        total_size += x;
+            let ser_buf = buf.split();
+            let s = ser_buf.freeze();
+            let x = s.len();
+            latency.record_duration(op_start.elapsed());
+            // buf.extend_from_slice(&d[..]);
+            // buf.extend_from_slice(extend)
+            // let ser_buf = buf.split();
+            // println!("len: {}, capacity: {}", ser_buf.len(), ser_buf.capacity());
+            // break;
+            // buf.extend_from_slice(&d.len().to_le_bytes());
+            // buf.extend_from_slice(&d[..]);
+            s_batch += batch_size;
+            e_batch += batch_size;
+            total_size += x;
+            progress.record(x);
+        }
+        // time-bounded runs start over at the beginning of the data
+        if !progress.repeat() {
+            break;
+        }
+        s_batch = 0;
+        e_batch = batch_size;
     }
     let end = SystemTime::now();
-    let duration = (end.duration_since(start).unwrap().as_micros() as f64) / 1e6;
-    let total_size = total_size as f64 / (1024f64 * 1024f64);
-    let throughput = total_size / duration;
-    println!(
-        "duration: {}s, bincode serialize throughput: {:.3}MB/s",
-        duration, throughput
-    );
+    Sample::new(total_size, end.duration_since(start).unwrap()).with_latency(latency)
 }
 
-pub fn test_serialize(in_path: &str, batch_size: usize) {
-    let mut file = File::open(in_path).expect("Unable to open input file");
+/// `bincode_serialize` of the content of a file.
+pub fn test_serialize(in_path: &str, batch_size: usize) -> Result<Sample> {
+    let data = load(in_path)?;
+    Ok(bincode_serialize::<u8>(&data, batch_size, &Progress::new()))
+}
+
+fn load(in_path: &str) -> Result<Vec<u8>> {
+    let mut file = File::open(in_path)?;
     let mut data = Vec::<u8>::with_capacity(10240);
-    file.read_to_end(&mut data).unwrap();
-    bincode_serialize::<u8>(&data, batch_size);
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[derive(Args, Serialize, Debug)]
+pub struct SerializeArgs {
+    /// File whose content is serialized.
+    #[clap(long, default_value = "data/bigfile.log")]
+    path: String,
+    /// Bytes per serialized batch.
+    #[clap(long, default_value_t = 1024)]
+    batch_size: usize,
+    #[clap(flatten)]
+    #[serde(flatten)]
+    data: InputArgs,
+}
+
+pub struct SerializeBench {
+    args: SerializeArgs,
+    data: Vec<u8>,
+}
+
+impl Benchmark for SerializeBench {
+    fn setup(&mut self) -> Result<()> {
+        self.data = self.args.data.load(&self.args.path, None)?;
+        Ok(())
+    }
+    fn run(&mut self, progress: &Progress) -> Result<Sample> {
+        Ok(bincode_serialize::<u8>(&self.data, self.args.batch_size, progress))
+    }
+    fn teardown(&mut self) -> Result<()> {
+        self.data = Vec::new();
+        Ok(())
+    }
+    fn describe(&self, env: &mut Environment) {
+        env.add_path(&self.args.path);
+    }
+}
+
+impl BenchmarkArgs for SerializeArgs {
+    fn build(self) -> Box<dyn Benchmark> {
+        Box::new(SerializeBench {
+            args: self,
+            data: Vec::new(),
+        })
+    }
 }
@@ -0,0 +1,135 @@
+use std::{fs::File, io, path::Path};
+
+use clap::Args;
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, Result},
+    result::{OutputFormat, Parameters},
+};
+
+#[derive(Args, Debug)]
+pub struct SuiteArgs {
+    /// Scenario file (JSON).
+    scenario: String,
+}
+
+impl SuiteArgs {
+    pub fn load(&self) -> Result<Scenario> {
+        Scenario::load(&self.scenario)
+    }
+}
+
+// A benchmark suite, e.g.
+//
+// {
+//   "name": "nightly-disk",
+//   "data_files": ["data/bigfile.log"],
+//   "output": "results/nightly.json",
+//   "format": "json",
+//   "repetitions": 10,
+//   "steps": [
+//     { "benchmark": "disk-read", "parameters": { "buf_len": "1K..4M:x4" } },
+//     { "benchmark": "disk-write", "parameters": { "buf_len": [102400, 4096000] },
+//       "repetitions": 3, "output": "results/write.json" }
+//   ]
+// }
+//
+// Parameters are the long options of the benchmark with `_` or `-`. Arrays and
+// strings accept the sweep syntax, `true` passes a flag.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: Option<String>,
+    // Input files that must exist before any step runs.
+    #[serde(default)]
+    pub data_files: Vec<String>,
+    // Consolidated report of every step.
+    pub output: Option<String>,
+    pub format: Option<OutputFormat>,
+    pub warmup: Option<usize>,
+    pub repetitions: Option<usize>,
+    pub target_time: Option<f64>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub benchmark: String,
+    #[serde(default)]
+    pub parameters: Parameters,
+    pub warmup: Option<usize>,
+    pub repetitions: Option<usize>,
+    pub target_time: Option<f64>,
+    // Results of this step only, always JSON.
+    pub output: Option<String>,
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Self> {
+        let file = File::open(path)?;
+        serde_json::from_reader(io::BufReader::new(file))
+            .map_err(|e| Error::Config(format!("scenario {}: {}", path, e)))
+    }
+
+    pub fn check_data_files(&self) -> Result<()> {
+        for path in self.data_files.iter() {
+            if !Path::new(path).exists() {
+                return Err(Error::Config(format!("data file {} does not exist", path)));
+            }
+        }
+        Ok(())
+    }
+
+    // Command line equivalent to a step, starting with the program name.
+    pub fn command_line(&self, step: &Step) -> Vec<String> {
+        let mut argv = vec![
+            std::env::args().next().unwrap_or_default(),
+            step.benchmark.clone(),
+        ];
+        let measure = [
+            ("warmup", step.warmup.or(self.warmup).map(|v| v.to_string())),
+            (
+                "repetitions",
+                step.repetitions.or(self.repetitions).map(|v| v.to_string()),
+            ),
+            (
+                "target_time",
+                step.target_time.or(self.target_time).map(|v| v.to_string()),
+            ),
+        ];
+        for (name, value) in measure.iter() {
+            if let Some(value) = value {
+                argv.push(option(name));
+                argv.push(value.clone());
+            }
+        }
+        for (name, value) in step.parameters.iter() {
+            let value = match value {
+                serde_json::Value::Bool(true) => {
+                    argv.push(option(name));
+                    continue;
+                }
+                serde_json::Value::Bool(false) | serde_json::Value::Null => continue,
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Array(values) => values
+                    .iter()
+                    .map(|v| match v {
+                        serde_json::Value::String(s) => s.clone(),
+                        v => v.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+                v => v.to_string(),
+            };
+            argv.push(option(name));
+            argv.push(value);
+        }
+        argv
+    }
+}
+
+fn option(name: &str) -> String {
+    format!("--{}", name.replace('_', "-"))
+}
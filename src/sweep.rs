@@ -0,0 +1,207 @@
+// Sweep syntax for numeric options, e.g. `--buf-len 1K,2K,4K..4M:x2`.
+//
+// A sweep is a comma separated list of items. An item is a size with an
+// optional K/M/G suffix (powers of 1024) or an inclusive range `a..b` with a
+// multiplicative `:xN` or additive `:+N` step. Ranges default to `:x2`.
+
+pub fn parse_size(s: &str) -> Option<u64> {
+    let (digits, unit) = match s.char_indices().last()? {
+        (i, 'K') | (i, 'k') => (&s[..i], 1 << 10),
+        (i, 'M') | (i, 'm') => (&s[..i], 1 << 20),
+        (i, 'G') | (i, 'g') => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+pub fn parse_sweep(s: &str) -> Option<Vec<u64>> {
+    let mut values = Vec::new();
+    for item in s.split(',') {
+        let (range, step) = match item.find(':') {
+            Some(i) => (&item[..i], Some(&item[i + 1..])),
+            None => (item, None),
+        };
+        let (start, end) = match range.find("..") {
+            Some(i) => (parse_size(&range[..i])?, parse_size(&range[i + 2..])?),
+            None if step.is_none() => {
+                values.push(parse_size(range)?);
+                continue;
+            }
+            None => return None,
+        };
+        let step = step.unwrap_or("x2");
+        let next: Box<dyn Fn(u64) -> u64> = if let Some(factor) = step.strip_prefix('x') {
+            let factor = parse_size(factor).filter(|f| *f > 1)?;
+            Box::new(move |v| v.saturating_mul(factor))
+        } else if let Some(inc) = step.strip_prefix('+') {
+            let inc = parse_size(inc).filter(|i| *i > 0)?;
+            Box::new(move |v| v.saturating_add(inc))
+        } else {
+            return None;
+        };
+        if start == 0 || start > end {
+            return None;
+        }
+        let mut v = start;
+        while v <= end {
+            values.push(v);
+            let n = next(v);
+            if n == v {
+                break;
+            }
+            v = n;
+        }
+    }
+    Some(values)
+}
+
+// A value is only rewritten if it uses the sweep syntax or a size suffix, so
+// paths, addresses and plain numbers pass through untouched.
+fn sweep_values(value: &str) -> Option<Vec<u64>> {
+    if value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    parse_sweep(value)
+}
+
+// Options whose values are lists of their own, e.g. `--cpu-list 0,1,2,3` is one
+// run on four CPUs and not four runs on one CPU each, and durations, whose
+// `m` is minutes and not MiB.
+const NOT_SWEPT: &[&str] = &["--cpu-list", "--duration", "--report-interval"];
+
+// Expands every swept option of a command line into the cartesian product of
+// command lines with one value per option.
+pub fn expand_args(args: &[String]) -> Vec<Vec<String>> {
+    let mut variants: Vec<Vec<String>> = vec![Vec::new()];
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        let mut push_all = |tokens: &[String]| {
+            for v in variants.iter_mut() {
+                v.extend_from_slice(tokens);
+            }
+        };
+        if !arg.starts_with("--") || i == 0 {
+            push_all(std::slice::from_ref(arg));
+            i += 1;
+            continue;
+        }
+        // `--name=value` or `--name value`
+        let (name, value, consumed) = match arg.find('=') {
+            Some(eq) => (arg[..eq].to_string(), Some(arg[eq + 1..].to_string()), 1),
+            None => match args.get(i + 1) {
+                Some(next) if !next.starts_with("--") => (arg.clone(), Some(next.clone()), 2),
+                _ => (arg.clone(), None, 1),
+            },
+        };
+        let swept = value
+            .as_deref()
+            .filter(|_| !NOT_SWEPT.contains(&name.as_str()))
+            .and_then(sweep_values);
+        match swept {
+            Some(values) => {
+                let mut expanded = Vec::with_capacity(variants.len() * values.len());
+                for v in variants.iter() {
+                    for value in values.iter() {
+                        let mut v = v.clone();
+                        v.push(name.clone());
+                        v.push(value.to_string());
+                        expanded.push(v);
+                    }
+                }
+                variants = expanded;
+            }
+            None => push_all(&args[i..i + consumed]),
+        }
+        i += consumed;
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("4K"), Some(4096));
+        assert_eq!(parse_size("4k"), Some(4096));
+        assert_eq!(parse_size("3M"), Some(3 << 20));
+        assert_eq!(parse_size("2G"), Some(2 << 30));
+        for s in ["", "K", "1.5K", "-1", "1T", "1 K", "0x10"] {
+            assert_eq!(parse_size(s), None, "{}", s);
+        }
+        assert_eq!(parse_size("17179869184G"), None);
+    }
+
+    #[test]
+    fn sweeps() {
+        assert_eq!(parse_sweep("1K,2K,4K"), Some(vec![1024, 2048, 4096]));
+        assert_eq!(parse_sweep("1K..8K"), Some(vec![1024, 2048, 4096, 8192]));
+        assert_eq!(parse_sweep("1..100:x10"), Some(vec![1, 10, 100]));
+        assert_eq!(parse_sweep("1..10:+4"), Some(vec![1, 5, 9]));
+        assert_eq!(parse_sweep("3,1..4:x2"), Some(vec![3, 1, 2, 4]));
+        assert_eq!(parse_sweep("5..5"), Some(vec![5]));
+        for s in ["", "1,,2", "0..8", "8..1", "1..8:x1", "1..8:+0", "1..8:*2", "4:x2", "1..x"] {
+            assert_eq!(parse_sweep(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn only_sweeps_and_sizes_are_swept() {
+        assert_eq!(sweep_values("1024"), None);
+        assert_eq!(sweep_values("1K"), Some(vec![1024]));
+        assert_eq!(sweep_values("1,2"), Some(vec![1, 2]));
+        for s in ["data/bigfile.log", "0.0.0.0:9500", "random", "0.99"] {
+            assert_eq!(sweep_values(s), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn cartesian_product() {
+        assert_eq!(
+            expand_args(&args("benchmark disk-read --buf-len 1K,2K --threads=1,2 --direct")),
+            vec![
+                args("benchmark disk-read --buf-len 1024 --threads 1 --direct"),
+                args("benchmark disk-read --buf-len 1024 --threads 2 --direct"),
+                args("benchmark disk-read --buf-len 2048 --threads 1 --direct"),
+                args("benchmark disk-read --buf-len 2048 --threads 2 --direct"),
+            ]
+        );
+        assert_eq!(
+            expand_args(&args("benchmark disk-read --repetitions 5")),
+            vec![args("benchmark disk-read --repetitions 5")]
+        );
+    }
+
+    #[test]
+    fn durations_are_not_sizes() {
+        assert_eq!(
+            expand_args(&args("benchmark disk-read --duration 5m --report-interval=1m")),
+            vec![args("benchmark disk-read --duration 5m --report-interval=1m")]
+        );
+    }
+
+    #[test]
+    fn cpu_list_is_not_swept() {
+        assert_eq!(
+            expand_args(&args("benchmark disk-read --cpu-list 0,1,2,3")),
+            vec![args("benchmark disk-read --cpu-list 0,1,2,3")]
+        );
+        assert_eq!(
+            expand_args(&args("benchmark disk-read --cpu-list=0,1 --threads 1,2")),
+            vec![
+                args("benchmark disk-read --cpu-list=0,1 --threads 1"),
+                args("benchmark disk-read --cpu-list=0,1 --threads 2"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! CPU time, page faults and context switches of measured iterations, from
+//! getrusage and /proc/thread-self/stat. Workloads run on the thread that
+//! measures them, so the counters of that thread are what the workload cost,
+//! including the kernel time of its system calls and busy polling.
+use std::{fmt, fs};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub user_s: f64,
+    pub system_s: f64,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    pub voluntary_switches: u64,
+    pub involuntary_switches: u64,
+    /// Time spent waiting for block IO, zero unless the kernel does delay
+    /// accounting.
+    pub blkio_wait_s: f64,
+    /// Peak resident set size of the process in KiB. Unlike the other fields
+    /// this is not a difference.
+    pub max_rss_kb: u64,
+}
+
+impl Usage {
+    /// The counters of the calling thread so far.
+    pub fn now() -> Self {
+        let mut ru = unsafe { std::mem::zeroed::<libc::rusage>() };
+        if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut ru) } != 0 {
+            return Self::default();
+        }
+        let secs = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+        Self {
+            user_s: secs(ru.ru_utime),
+            system_s: secs(ru.ru_stime),
+            minor_faults: ru.ru_minflt as u64,
+            major_faults: ru.ru_majflt as u64,
+            voluntary_switches: ru.ru_nvcsw as u64,
+            involuntary_switches: ru.ru_nivcsw as u64,
+            blkio_wait_s: blkio_ticks() as f64 / clock_ticks(),
+            max_rss_kb: ru.ru_maxrss as u64,
+        }
+    }
+
+    /// What was used since `start`, an earlier snapshot of the same thread.
+    pub fn since(&self, start: &Usage) -> Self {
+        Self {
+            user_s: self.user_s - start.user_s,
+            system_s: self.system_s - start.system_s,
+            minor_faults: self.minor_faults - start.minor_faults,
+            major_faults: self.major_faults - start.major_faults,
+            voluntary_switches: self.voluntary_switches - start.voluntary_switches,
+            involuntary_switches: self.involuntary_switches - start.involuntary_switches,
+            blkio_wait_s: self.blkio_wait_s - start.blkio_wait_s,
+            max_rss_kb: self.max_rss_kb,
+        }
+    }
+
+    /// Adds the usage of another iteration or thread.
+    pub fn add(&mut self, other: &Usage) {
+        self.user_s += other.user_s;
+        self.system_s += other.system_s;
+        self.minor_faults += other.minor_faults;
+        self.major_faults += other.major_faults;
+        self.voluntary_switches += other.voluntary_switches;
+        self.involuntary_switches += other.involuntary_switches;
+        self.blkio_wait_s += other.blkio_wait_s;
+        self.max_rss_kb = self.max_rss_kb.max(other.max_rss_kb);
+    }
+
+    pub fn cpu_s(&self) -> f64 {
+        self.user_s + self.system_s
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cpu(s): user {:.3}, sys {:.3}, faults: minor {}, major {}, context switches: voluntary {}, involuntary {}, max rss(KiB): {}",
+            self.user_s,
+            self.system_s,
+            self.minor_faults,
+            self.major_faults,
+            self.voluntary_switches,
+            self.involuntary_switches,
+            self.max_rss_kb
+        )?;
+        if self.blkio_wait_s > 0f64 {
+            write!(f, ", blkio wait(s): {:.3}", self.blkio_wait_s)?;
+        }
+        Ok(())
+    }
+}
+
+fn clock_ticks() -> f64 {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as f64,
+        _ => 100f64,
+    }
+}
+
+// delayacct_blkio_ticks, field 42 of /proc/[pid]/stat. The fields are counted
+// after the command name, which may contain spaces.
+fn blkio_ticks() -> u64 {
+    let stat = match fs::read_to_string("/proc/thread-self/stat") {
+        Ok(stat) => stat,
+        Err(_) => return 0,
+    };
+    stat.rsplit_once(')')
+        .and_then(|(_, fields)| fields.split_whitespace().nth(42 - 3))
+        .and_then(|ticks| ticks.parse().ok())
+        .unwrap_or(0)
+}
@@ -0,0 +1,114 @@
+//! Runs the tcp pair through two agents on loopback, the way `coordinate` runs
+//! benchmark pairs on two hosts.
+use std::{
+    env,
+    io::{self, BufRead, BufReader},
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    thread,
+};
+
+use benchmark::coordinator::{self, CoordinateArgs};
+use clap::Parser;
+
+#[derive(Parser)]
+struct Cli {
+    #[clap(flatten)]
+    args: CoordinateArgs,
+}
+
+// An agent process, killed when the test ends.
+struct Agent {
+    child: Child,
+    addr: String,
+}
+
+impl Agent {
+    fn start(extra: &[&str]) -> Self {
+        let addr = free_addr();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_benchmark"))
+            .args(["agent", "--listen", &addr])
+            .args(extra)
+            .current_dir(env::temp_dir())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        assert!(line.starts_with("agent listening"), "{}", line);
+        // The benchmarks of the agent print to the same pipe.
+        thread::spawn(move || io::copy(&mut stdout, &mut io::sink()));
+        Self { child, addr }
+    }
+}
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+fn coordinate_args(server: &Agent, client: &Agent, extra: &[&str]) -> CoordinateArgs {
+    let data_addr = free_addr();
+    let server_args = format!("--listen {}", data_addr);
+    let client_args = format!("--addr {} --generate random --data-size 1M --buf-len 64K,256K", data_addr);
+    let mut argv = vec![
+        "coordinate",
+        "tcp",
+        "--server-agent",
+        &server.addr,
+        "--client-agent",
+        &client.addr,
+        "--server-args",
+        &server_args,
+        "--client-args",
+        &client_args,
+        "--args",
+        "--warmup 0 --repetitions 2",
+    ];
+    argv.extend_from_slice(extra);
+    Cli::parse_from(argv).args
+}
+
+#[test]
+fn tcp_pair_on_two_agents() {
+    let server = Agent::start(&[]);
+    let client = Agent::start(&[]);
+    let results = coordinator::coordinate(&benchmark::registry(), &coordinate_args(&server, &client, &[]))
+        .unwrap();
+    // Server and client of both sweep points.
+    let names: Vec<_> = results.iter().map(|r| r.benchmark.as_str()).collect();
+    assert_eq!(names, ["tcp-server", "tcp-client", "tcp-server", "tcp-client"]);
+    for result in results.iter() {
+        assert_eq!(result.total_bytes, 2 * 1024 * 1024);
+    }
+}
+
+#[test]
+fn agents_check_the_token() {
+    let server = Agent::start(&["--token", "secret"]);
+    let client = Agent::start(&["--token", "secret"]);
+    let registry = benchmark::registry();
+    let err = coordinator::coordinate(&registry, &coordinate_args(&server, &client, &[])).unwrap_err();
+    assert!(err.to_string().contains("token"), "{}", err);
+    let args = coordinate_args(&server, &client, &["--token", "secret"]);
+    assert_eq!(coordinator::coordinate(&registry, &args).unwrap().len(), 4);
+}
+
+#[test]
+fn agents_stay_on_loopback_without_a_token() {
+    let output = Command::new(env!("CARGO_BIN_EXE_benchmark"))
+        .args(["agent", "--listen", "0.0.0.0:0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--allow-remote"));
+}